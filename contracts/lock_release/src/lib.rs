@@ -1,20 +1,173 @@
 #![no_std]
+#![allow(clippy::too_many_arguments)]
+
+mod test;
 
 use soroban_sdk::{
-    contract, contractimpl, contracttype, token, xdr::ScErrorCode, xdr::ScErrorType, Address,
-    Bytes, Env, Error, String,
+    contract, contracterror, contractimpl, contracttype, panic_with_error, token, xdr::ToXdr,
+    Address, Bytes, BytesN, Env, IntoVal, InvokeError, Map, String, Symbol, Topics, Val, Vec,
 };
 
+#[contracterror]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum LockError {
+    AlreadyInitialized = 1,
+    AdminNotSet = 2,
+    AdminAlreadySet = 3,
+    InvalidAmount = 4,
+    InsufficientAdminBalance = 5,
+    ReentrantCall = 6,
+    ContractPaused = 7,
+    AlreadyPaused = 8,
+    NotPaused = 9,
+    LockNotFound = 10,
+    LockAlreadyExists = 11,
+    InvalidLockStatus = 12,
+    ChainNotFound = 13,
+    ReceiverRejected = 14,
+    RecipientBlocked = 15,
+    RecipientCapExceeded = 16,
+    InvalidMemo = 17,
+    AmountOverflow = 18,
+    RoleSeparationViolated = 19,
+    InvalidConfig = 20,
+    InvalidFee = 21,
+    TooManyItems = 22,
+    AllowListFull = 23,
+    TokenAlreadyAllowed = 24,
+    UnsupportedToken = 25,
+    InvalidRefundFallback = 26,
+    InvalidPriority = 27,
+    TokenPaused = 28,
+    InsufficientBalance = 29,
+    UnknownDestToken = 30,
+    ContractSunset = 31,
+    TooManyPendingLocks = 32,
+    DuplicateLock = 33,
+    SwapAmountTooSmall = 34,
+    RefundFailed = 35,
+    EmptyBatch = 36,
+    IneligibleForBatch = 37,
+    BatchNotFound = 38,
+    MigrationFinalized = 39,
+    ProofVerifierNotSet = 40,
+    InvalidProof = 41,
+    InsufficientLiquidity = 42,
+    NothingToClaim = 43,
+    ReleaseNotFound = 44,
+    ReversalNotAllowed = 45,
+    RecipientFundsMissing = 46,
+    BatchTooLarge = 47,
+    NotAdmin = 48,
+    AlreadyReleased = 49,
+    NoPendingOwner = 50,
+}
+
+/// `LockError` is at the variant limit for `contracterror` enums, so newer
+/// errors continue its numbering here.
+#[contracterror]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum ExtraError {
+    NotInitialized = 51,
+    DestTokenMismatch = 52,
+    AmountBelowMinimum = 53,
+    AmountAboveMaximum = 54,
+    LockTooYoung = 55,
+    UnsupportedChain = 56,
+    ChainAlreadyRegistered = 57,
+    InvalidReleaseScheme = 58,
+    ReleaseNotAuthorized = 59,
+    DailyCapExceeded = 60,
+    ForwardRetriesExhausted = 61,
+    NotRoleAdmin = 62,
+    RefundGraceActive = 63,
+    PartiallyReleased = 64,
+    InvalidCreatedLedger = 65,
+}
+
 #[derive(Clone)]
 #[contracttype]
 pub enum DataKey {
     Init,
     Owner,
+    PendingOwner,
     Admin,
-    LockData,
+    Admins,
+    LockCounter,
+    Lock(u64),
+    LockStatus(u64),
+    RecipientOverride(u64),
+    StatusCounts,
+    ProofVerifier,
+    ChainByName(String),
+    ChainName(Bytes),
+    KnownDestToken(String),
+    ChainAdmin(Bytes),
+    ReleaseCounter,
+    Release(u64),
+    ReleaseCallback,
+    GlobalSeq,
+    DailyVolume(Address),
+    DynamicFee,
+    MemoSchema,
+    TokenRegistry,
+    AllowedTokens,
+    KnownToken(Address),
+    PendingCount(Address),
+    BatchCounter,
+    Batch(u64),
+    LockBatch(u64),
+    LockSettlement(u64),
+    ReleasedAmount(u64),
+    Activity,
+    FeeStats,
+    BlockedRecipient(Address),
+    ReceiverContract(Address),
+    AdminReserve(Address),
+    RecipientReleased(Address),
+    MigrationComplete,
+    RelayerAccrued(Address, Address),
+    FeeRecipient,
+    AccruedFees(Address),
+    ProtocolRemainder(Address),
     Config,
+    CodeHash,
     ReentrancyGuard,
     Paused, 
+    TokenConfig(Address),
+    TokenCustodian(Address),
+    TokenPauseInfo(Address),
+    LastLock(Address),
+}
+
+/// `DataKey` is at the variant limit for `contracttype` enums, so newer keys
+/// live here. Names must not repeat a `DataKey` variant, as both encode as the
+/// variant name followed by its fields.
+#[derive(Clone)]
+#[contracttype]
+pub enum ExtraKey {
+    UserStats(Address),
+    DestTokenAddress(String),
+    FeeRecipientFallbacks,
+    PauseHistory,
+    UserLocks(Address),
+    LockAgeWaived(u64),
+    UniqueUsers,
+    SupportedChains,
+    BridgeCommitment(u64),
+    ReleaseCap(Address),
+    ReleasedToday(Address),
+    ForwardState(u64),
+    PausedTokens,
+    ReleaseAuth(u64),
+    RoleManager,
+    RefundGrace(u64),
+    RelayerOwed(Address),
+    PrincipalHeld(u64),
+    ChainAdminChains,
+    HeldPrincipal(Address),
 }
 
 #[derive(Clone)]
@@ -27,6 +180,81 @@ pub struct LockData {
     pub swaped_amount: i128,
     pub recipient_address: String,
     pub dest_chain: Bytes,
+    pub created_ledger: u32,
+    pub refund_fallback: Option<Address>,
+    pub fee_scaled_applied: i128,
+    pub memo: Option<Bytes>,
+    pub priority: u32,
+    pub remainder_charged: i128,
+    /// Who the fee was forwarded to, or None while it is accrued in custody
+    pub fee_payee: Option<Address>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum LockStatus {
+    PendingApproval,
+    Pending,
+    Processing,
+    Released,
+    Refunded,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct StatusCounts {
+    pub pending_approval: u64,
+    pub pending: u64,
+    pub processing: u64,
+    pub released: u64,
+    pub refunded: u64,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct RecipientOverride {
+    pub original_recipient: String,
+    pub ledger: u32,
+}
+
+/// A completed release. `source` is where the funds came from and where a
+/// reversal returns them.
+#[derive(Clone)]
+#[contracttype]
+pub struct ReleaseRecord {
+    pub lock_id: u64,
+    pub recipient: Address,
+    pub token: Address,
+    pub amount: i128,
+    pub source: Address,
+    pub ledger: u32,
+    pub timestamp: u64,
+    pub reversed: bool,
+}
+
+/// Where a lock's funds finally went, written when it is released or refunded.
+#[derive(Clone)]
+#[contracttype]
+pub struct LockSettlement {
+    pub ledger: u32,
+    pub settled_to: Address,
+    pub amount: i128,
+}
+
+/// Everything recorded about one lock. Optional records are flattened since
+/// nested optional structs don't round-trip through `contracttype`.
+#[derive(Clone)]
+#[contracttype]
+pub struct LockAudit {
+    pub lock_data: LockData,
+    pub status: LockStatus,
+    pub batch_id: Option<u64>,
+    pub original_recipient: Option<String>,
+    pub override_ledger: Option<u32>,
+    pub settled_ledger: Option<u32>,
+    pub settled_to: Option<Address>,
+    pub settled_amount: i128,
+    pub released_amount: i128,
 }
 
 #[derive(Clone)]
@@ -38,18 +266,267 @@ pub struct AdminData {
 #[derive(Clone)]
 #[contracttype]
 pub struct Config {
-    pub fee_percentage: i128,
+    pub fee_scaled: i128,
+    pub dup_window_ledgers: u32,
+    pub min_confirmations: u32,
+    pub recipient_lifetime_cap: i128,
+    pub relayer_reimbursement_bps: i128,
+    pub max_pending_per_user: u32,
+    pub require_role_separation: bool,
+    pub cap_warning_percent: u32,
+    pub high_value_threshold: i128,
+    pub forward_dust_threshold: i128,
+    pub sunset_ledger: u32,
+    pub validate_dest_token: bool,
+    pub dispute_window_ledgers: u32,
+    pub max_batch_size: u32,
+    pub refund_fee_bps: i128,
+    pub enforce_dest_token_match: bool,
+    pub min_lock: i128,
+    pub max_lock: i128,
+    pub min_lock_age_ledgers: u32,
+    pub hold_principal: bool,
+}
+
+/// Volume-responsive fee: the fee rises linearly from `min_fee_bps` with no
+/// volume to `max_fee_bps` once the token's volume for the current day reaches
+/// `target_daily_volume`, and stays there beyond it.
+#[derive(Clone)]
+#[contracttype]
+pub struct DynamicFee {
+    pub min_fee_bps: i128,
+    pub max_fee_bps: i128,
+    pub target_daily_volume: i128,
+}
+
+/// Minimum shape a lock memo must have once registered. A `max_len` of zero
+/// leaves the length unbounded and an empty `prefix` accepts any memo.
+#[derive(Clone)]
+#[contracttype]
+pub struct MemoSchema {
+    pub min_len: u32,
+    pub max_len: u32,
+    pub prefix: Bytes,
+}
+
+/// Remaining headroom under each active cap. `None` means the cap is unset.
+#[derive(Clone)]
+#[contracttype]
+pub struct Capacity {
+    pub releasable: i128,
+    pub pending_slots: Option<u32>,
+    pub recipient_lifetime: Option<i128>,
+    pub daily_release: Option<i128>,
+}
+
+/// Every bound the contract currently enforces on input. `None` means the limit
+/// is unset. Per-token minimums live in `TokenConfig`.
+#[derive(Clone)]
+#[contracttype]
+pub struct Limits {
+    pub dup_window_ledgers: Option<u32>,
+    pub min_confirmations: Option<u32>,
+    pub max_pending_per_user: Option<u32>,
+    pub recipient_lifetime_cap: Option<i128>,
+    pub high_value_threshold: Option<i128>,
+    pub sunset_ledger: Option<u32>,
+    pub max_priority: u32,
+    pub max_query_len: u32,
+    pub max_allowed_tokens: u32,
+    pub max_batch_size: u32,
+    pub min_lock: i128,
+    pub max_lock: Option<i128>,
+    pub min_lock_age_ledgers: Option<u32>,
+}
+
+/// What a lock would cost right now. `base_fee` is the global rate; a token
+/// override or the dynamic fee shows up as a `surcharge` or `discount` on it.
+#[derive(Clone)]
+#[contracttype]
+pub struct CostBreakdown {
+    pub in_amount: i128,
+    pub base_fee: i128,
+    pub surcharge: i128,
+    pub discount: i128,
+    pub fee: i128,
+    pub remainder: i128,
+    pub net_out: i128,
+}
+
+/// Predicted outcome of locking and then fully releasing: the record `lock`
+/// would store under `lock_id` and what the release would pay the recipient.
+#[derive(Clone)]
+#[contracttype]
+pub struct RoundTripResult {
+    pub lock_id: u64,
+    pub lock_data: LockData,
+    pub requires_approval: bool,
+    pub release_amount: i128,
+}
+
+/// One entry of a `release_batch`, with the same meaning as the arguments of
+/// `release`.
+#[derive(Clone)]
+#[contracttype]
+pub struct ReleaseRequest {
+    pub lock_id: u64,
+    pub amount: i128,
+    pub user: Address,
+    pub destination_token: Address,
+}
+
+/// Who besides the releasing admin must approve releases to a registered
+/// chain. `Multisig` needs `m` distinct approvals from its signers, and
+/// `OracleSigned` an ed25519 signature by the key over
+/// `(contract, lock_id, released_amount, amount, user, token)`, where
+/// `released_amount` is what the lock had paid out before this release, so a
+/// signature authorizes one release only.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum ReleaseScheme {
+    SingleAdmin,
+    Multisig(u32, Vec<Address>),
+    OracleSigned(BytesN<32>),
 }
 
+/// The approval a release carries for its chain's `ReleaseScheme`.
+#[derive(Clone)]
+#[contracttype]
+pub enum ReleaseApproval {
+    AdminOnly,
+    Signers(Vec<Address>),
+    OracleSignature(BytesN<64>),
+}
+
+/// A lock fee that no payee accepted at lock time and was held in custody
+/// instead. `forwarded_to` is set once a `retry_forward` delivers it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ForwardState {
+    pub fee: i128,
+    pub attempts: u32,
+    pub last_attempt_ledger: u32,
+    pub forwarded_to: Option<Address>,
+}
+
+/// One pause or unpause of the whole contract.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PauseRecord {
+    pub paused: bool,
+    pub ledger: u32,
+    pub actor: Address,
+}
+
+/// A governance change that has been scheduled but has not taken effect yet.
+#[derive(Clone)]
+#[contracttype]
+pub struct PendingAction {
+    pub kind: Symbol,
+    pub effective_ledger: u32,
+}
+
+/// Cheap liveness readout. The daily counters cover the UTC day `day`
+/// (ledger timestamp / 86400).
+#[derive(Clone)]
+#[contracttype]
+pub struct Activity {
+    pub last_lock_ledger: u32,
+    pub last_release_ledger: u32,
+    pub day: u64,
+    pub locks_today: u32,
+    pub releases_today: u32,
+}
+
+/// Lifetime totals across every token locked, in raw token units.
+#[derive(Clone)]
+#[contracttype]
+pub struct FeeStats {
+    pub total_in: i128,
+    pub total_fees: i128,
+}
+
+/// One address's lifetime activity, in raw token units summed across tokens.
+/// `total_released` counts releases received as a recipient.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct UserStats {
+    pub total_locked: i128,
+    pub total_released: i128,
+    pub lock_count: u64,
+    pub fees_paid: i128,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct DailyVolume {
+    pub day: u64,
+    pub volume: i128,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct LastLock {
+    pub hash: BytesN<32>,
+    pub ledger: u32,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct TokenConfig {
+    /// Overrides the global fee for this token, in hundredths of a basis point
+    pub fee_scaled: Option<i128>,
+    pub paused: bool,
+    /// Read from the token contract; a value passed to `set_token_config` is ignored
+    pub decimals: u32,
+    pub low_balance_threshold: i128,
+    pub min_swaped: i128,
+}
+
+// Fees are expressed in hundredths of a basis point, so 1_000_000 is 100%
+const FEE_SCALE: i128 = 1_000_000;
+
+// Upper bound on ids accepted or returned by a single query
+const MAX_QUERY_LEN: u32 = 100;
+
+// Upper bound on the size of the token allow-list
+const MAX_ALLOWED_TOKENS: u32 = 50;
+
+// Highest priority a lock may be flagged with; 0 is normal priority
+const MAX_PRIORITY: u32 = 10;
+
+// Number of pause and unpause records kept, oldest dropped first
+const MAX_PAUSE_HISTORY: u32 = 20;
+
+// Cap on the fee charged for cancelling a lock, in basis points
+const MAX_REFUND_FEE_BPS: i128 = 500;
+
+// Forward attempts allowed per lock fee, counting the one made at lock time
+const MAX_FORWARD_ATTEMPTS: u32 = 5;
+
 #[contract]
 pub struct LockAndReleaseContract;
 
+// Every event takes the next global sequence number, published alongside its
+// data as `(seq, data)`, giving consumers a total order over all activity.
+fn publish_event<T, D>(env: &Env, topics: T, data: D)
+where
+    T: Topics,
+    D: IntoVal<Env, Val>,
+{
+    let seq: u64 = env
+        .storage()
+        .instance()
+        .get(&DataKey::GlobalSeq)
+        .unwrap_or(0)
+        + 1;
+    env.storage().instance().set(&DataKey::GlobalSeq, &seq);
+    env.events().publish(topics, (seq, data.into_val(env)));
+}
+
 fn check_and_set_reentrancy_guard(env: &Env) {
     if env.storage().instance().has(&DataKey::ReentrancyGuard) {
-        env.panic_with_error(Error::from_type_and_code(
-            ScErrorType::Contract,
-            ScErrorCode::InvalidAction,
-        ));
+        panic_with_error!(env, LockError::ReentrantCall);
     }
     env.storage().instance().set(&DataKey::ReentrancyGuard, &());
 }
@@ -58,237 +535,4374 @@ fn clear_reentrancy_guard(env: &Env) {
     env.storage().instance().remove(&DataKey::ReentrancyGuard);
 }
 
-fn check_if_paused(env: &Env) {
-    if env.storage().instance().has(&DataKey::Paused) {
-        env.panic_with_error(Error::from_type_and_code(
-            ScErrorType::Contract,
-            ScErrorCode::InvalidAction,
-        ));
-    }
+fn read_token_config(env: &Env, token: &Address) -> TokenConfig {
+    env.storage()
+        .instance()
+        .get(&DataKey::TokenConfig(token.clone()))
+        .unwrap_or(TokenConfig {
+            fee_scaled: None,
+            paused: false,
+            decimals: 7,
+            low_balance_threshold: 0,
+            min_swaped: 1,
+        })
 }
 
-#[contractimpl]
-impl LockAndReleaseContract {
-    pub fn initialize(env: Env, owner: Address, fee_percentage: i128) {
-        if env.storage().instance().has(&DataKey::Init) {
-            env.panic_with_error(Error::from_type_and_code(
-                ScErrorType::Contract,
-                ScErrorCode::ExistingValue,
-            ));
+fn read_paused_tokens(env: &Env) -> Vec<Address> {
+    env.storage()
+        .instance()
+        .get(&ExtraKey::PausedTokens)
+        .unwrap_or(Vec::new(env))
+}
+
+// Stores `token_config`, keeping the paused-token index in step with its flag
+fn write_token_config(env: &Env, token: &Address, token_config: &TokenConfig) {
+    env.storage()
+        .instance()
+        .set(&DataKey::TokenConfig(token.clone()), token_config);
+
+    let mut paused_tokens = read_paused_tokens(env);
+    match (paused_tokens.first_index_of(token), token_config.paused) {
+        (None, true) => paused_tokens.push_back(token.clone()),
+        (Some(index), false) => {
+            paused_tokens.remove(index);
         }
+        _ => return,
+    }
+    env.storage()
+        .instance()
+        .set(&ExtraKey::PausedTokens, &paused_tokens);
+}
 
-        env.storage().instance().set(&DataKey::Owner, &owner);
-        env.storage().instance().set(&DataKey::Config, &Config { fee_percentage });
-        env.storage().instance().set(&DataKey::Init, &());
+// Where the contract keeps its holdings of `token`: the configured custodian,
+// or the contract itself.
+fn read_custodian(env: &Env, token: &Address) -> Address {
+    env.storage()
+        .instance()
+        .get(&DataKey::TokenCustodian(token.clone()))
+        .unwrap_or(env.current_contract_address())
+}
+
+// Pays out of custody. An external custodian must authorize the transfer.
+fn pay_from_custody(env: &Env, token: &Address, to: &Address, amount: i128) {
+    let custodian = read_custodian(env, token);
+    if custodian != env.current_contract_address() {
+        custodian.require_auth();
     }
+    token::Client::new(env, token).transfer(&custodian, to, &amount);
+}
 
-    pub fn add_admin(env: Env, admin: Address) {
-        check_if_paused(&env);
-        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
-        owner.require_auth();
-        
+fn read_allowed_tokens(env: &Env) -> Vec<Address> {
+    env.storage()
+        .instance()
+        .get(&DataKey::AllowedTokens)
+        .unwrap_or(Vec::new(env))
+}
 
-        if env.storage().instance().has(&DataKey::Admin) {  
-            env.panic_with_error(Error::from_type_and_code(
-                ScErrorType::Contract,
-                ScErrorCode::InvalidAction,
-            ));
-        }
+fn read_supported_chains(env: &Env) -> Map<Bytes, ReleaseScheme> {
+    env.storage()
+        .instance()
+        .get(&ExtraKey::SupportedChains)
+        .unwrap_or(Map::new(env))
+}
 
-        env.storage().instance().set(
-            &DataKey::Admin,
-            &AdminData {
-                admin_address: admin.clone(),
-            },
-        );
+// Appends `token` to the allow-list, returning false if it was already there.
+// Calling `decimals` rejects addresses that are not token contracts.
+fn push_allowed_token(env: &Env, allowed_tokens: &mut Vec<Address>, token: &Address) -> bool {
+    if allowed_tokens.contains(token) {
+        return false;
+    }
+    if allowed_tokens.len() >= MAX_ALLOWED_TOKENS {
+        panic_with_error!(env, LockError::AllowListFull);
+    }
+    token::Client::new(env, token).decimals();
+    allowed_tokens.push_back(token.clone());
+    true
+}
 
-        let topics = ("AdminAddedEvent", admin.clone());
-        env.events().publish(topics, AdminData { admin_address: admin });
+fn read_lock(env: &Env, lock_id: u64) -> LockData {
+    match env.storage().instance().get(&DataKey::Lock(lock_id)) {
+        Some(lock_data) => lock_data,
+        None => panic_with_error!(env, LockError::LockNotFound),
     }
+}
 
-    pub fn remove_admin(env: Env) {
-        check_if_paused(&env);
-        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
-        owner.require_auth();
+fn record_settlement(env: &Env, lock_id: u64, settled_to: &Address, amount: i128) {
+    env.storage().instance().set(
+        &DataKey::LockSettlement(lock_id),
+        &LockSettlement {
+            ledger: env.ledger().sequence(),
+            settled_to: settled_to.clone(),
+            amount,
+        },
+    );
+}
 
-        if !env.storage().instance().has(&DataKey::Admin) {
-            env.panic_with_error(Error::from_type_and_code(
-                ScErrorType::Contract,
-                ScErrorCode::MissingValue,
-            ));
-        }
+fn write_fee_scaled(env: &Env, fee_scaled: i128) {
+    let mut config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+    let old_fee_scaled = config.fee_scaled;
+    config.fee_scaled = fee_scaled;
+    env.storage().instance().set(&DataKey::Config, &config);
 
-        env.storage().instance().remove(&DataKey::Admin);
+    let topics = ("FeeUpdatedEvent", ());
+    publish_event(env, topics, (old_fee_scaled, fee_scaled));
+}
 
-        let topics = ("AdminRemovedEvent", ());
-        env.events().publish(topics, ());
-    }
+fn read_released_amount(env: &Env, lock_id: u64) -> i128 {
+    env.storage()
+        .instance()
+        .get(&DataKey::ReleasedAmount(lock_id))
+        .unwrap_or(0)
+}
 
-    pub fn pause(env: Env) {
-        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
-        owner.require_auth(); 
+// Marks a lock as fully paid out and announces the status change
+fn complete_release(env: &Env, lock_id: u64, recipient: &Address, amount: i128) {
+    let previous = read_lock_status(env, lock_id);
+    write_lock_status(env, lock_id, LockStatus::Released);
+    // Whatever principal the lock still held is no longer reserved
+    consume_held_principal(env, lock_id, amount);
+    record_settlement(env, lock_id, recipient, amount);
 
-        if env.storage().instance().has(&DataKey::Paused) {
-            env.panic_with_error(Error::from_type_and_code(
-                ScErrorType::Contract,
-                ScErrorCode::ExistingValue,
-            ));
-        }
+    let topics = ("LockStatusChangedEvent", lock_id);
+    publish_event(env, topics, (previous, LockStatus::Released));
+}
 
-        // Set the contract state to paused
-        env.storage().instance().set(&DataKey::Paused, &());
+fn read_lock_status(env: &Env, lock_id: u64) -> LockStatus {
+    env.storage()
+        .instance()
+        .get(&DataKey::LockStatus(lock_id))
+        .unwrap_or(LockStatus::Pending)
+}
 
-        let topics = ("ContractPausedEvent", ());
-        env.events().publish(topics, ());
+fn is_unsettled(status: LockStatus) -> bool {
+    matches!(
+        status,
+        LockStatus::PendingApproval | LockStatus::Pending | LockStatus::Processing
+    )
+}
+
+// High-value locks awaiting approval are unsettled but cannot be released yet
+fn is_releasable(status: LockStatus) -> bool {
+    matches!(status, LockStatus::Pending | LockStatus::Processing)
+}
+
+// A released lock is reported apart from other unreleasable ones so replays
+// are easy to tell from out-of-order calls.
+fn check_releasable(env: &Env, lock_id: u64) {
+    match read_lock_status(env, lock_id) {
+        LockStatus::Released => panic_with_error!(env, LockError::AlreadyReleased),
+        status if !is_releasable(status) => panic_with_error!(env, LockError::InvalidLockStatus),
+        _ => {}
     }
+}
 
-    pub fn unpause(env: Env) {
-        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
-        owner.require_auth(); 
+// A lock only becomes processable once it has aged `min_confirmations` ledgers
+// Imports can leave ids below the lock counter unassigned
+fn lock_exists(env: &Env, lock_id: u64) -> bool {
+    env.storage().instance().has(&DataKey::Lock(lock_id))
+}
 
-        if !env.storage().instance().has(&DataKey::Paused) {
-            env.panic_with_error(Error::from_type_and_code(
-                ScErrorType::Contract,
-                ScErrorCode::MissingValue,
-            ));
-        }
+fn is_confirmed(env: &Env, lock_data: &LockData) -> bool {
+    let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+    env.ledger().sequence() - lock_data.created_ledger >= config.min_confirmations
+}
 
-        // Set the contract state to unpaused
-        env.storage().instance().remove(&DataKey::Paused);
+fn read_status_counts(env: &Env) -> StatusCounts {
+    env.storage()
+        .instance()
+        .get(&DataKey::StatusCounts)
+        .unwrap_or(StatusCounts {
+            pending_approval: 0,
+            pending: 0,
+            processing: 0,
+            released: 0,
+            refunded: 0,
+        })
+}
 
-        let topics = ("ContractUnpausedEvent", ());
-        env.events().publish(topics, ());
+fn status_count_mut(counts: &mut StatusCounts, status: LockStatus) -> &mut u64 {
+    match status {
+        LockStatus::PendingApproval => &mut counts.pending_approval,
+        LockStatus::Pending => &mut counts.pending,
+        LockStatus::Processing => &mut counts.processing,
+        LockStatus::Released => &mut counts.released,
+        LockStatus::Refunded => &mut counts.refunded,
     }
+}
 
-    pub fn lock(
-        env: Env,
-        user_address: Address,
-        from_token: Address,
-        dest_token: String,
-        in_amount: i128,
-        dest_chain: Bytes,
-        recipient_address: String,
-    ) {
-        // Check if contract is paused before proceeding
-        check_if_paused(&env);
+fn read_pending_count(env: &Env, user: &Address) -> u32 {
+    env.storage()
+        .instance()
+        .get(&DataKey::PendingCount(user.clone()))
+        .unwrap_or(0)
+}
 
-        // Set re-entrancy guard
-        check_and_set_reentrancy_guard(&env);
-        
-        // Authorization and input validation
-        user_address.require_auth();
-        if in_amount < 1 {
-            env.panic_with_error(Error::from_type_and_code(
-                ScErrorType::Contract,
-                ScErrorCode::InvalidAction,
-            ));
-        }
+// Every status change goes through here so the per-status counters and the
+// per-user pending counts stay in sync
+fn write_lock_status(env: &Env, lock_id: u64, status: LockStatus) {
+    let mut counts = read_status_counts(env);
+    let previous: Option<LockStatus> = env.storage().instance().get(&DataKey::LockStatus(lock_id));
+    if let Some(previous) = previous {
+        *status_count_mut(&mut counts, previous) -= 1;
+    }
+    *status_count_mut(&mut counts, status) += 1;
 
-        // Check if an admin exists
-        if !env.storage().instance().has(&DataKey::Admin) {
-            env.panic_with_error(Error::from_type_and_code(
-                ScErrorType::Contract,
-                ScErrorCode::MissingValue,
-            ));
-        }
+    let was_unsettled = previous.is_some_and(is_unsettled);
+    if was_unsettled != is_unsettled(status) {
+        let user = read_lock(env, lock_id).user_address;
+        let pending_count = read_pending_count(env, &user);
+        let pending_count = if was_unsettled {
+            pending_count - 1
+        } else {
+            pending_count + 1
+        };
+        env.storage()
+            .instance()
+            .set(&DataKey::PendingCount(user), &pending_count);
+    }
 
-        // Verify user's balance before proceeding
-        let user_balance = token::Client::new(&env, &from_token).balance(&user_address);
-        if user_balance < in_amount {
-            env.panic_with_error(Error::from_type_and_code(
-                ScErrorType::Contract,
-                ScErrorCode::InvalidAction,
-            ));
-        }
-        
-        // Fee and swap calculations
-        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
-        let fee = in_amount * config.fee_percentage / 100;
-        let swaped_amount = in_amount - fee;
+    env.storage().instance().set(&DataKey::StatusCounts, &counts);
+    env.storage()
+        .instance()
+        .set(&DataKey::LockStatus(lock_id), &status);
+}
 
-        // Ensure valid swap amount after fee
-        if swaped_amount < 1 {
-            env.panic_with_error(Error::from_type_and_code(
-                ScErrorType::Contract,
-                ScErrorCode::InvalidAction,
-            ));
-        }
+fn resolve_chain_name(env: &Env, name: &String) -> Bytes {
+    match env.storage().instance().get(&DataKey::ChainByName(name.clone())) {
+        Some(dest_chain) => dest_chain,
+        None => panic_with_error!(env, LockError::ChainNotFound),
+    }
+}
 
-        // Update state before external interactions
-        env.storage().instance().set(
-            &DataKey::LockData,
-            &LockData {
-                user_address: user_address.clone(),
-                dest_token: dest_token.clone(),
-                from_token: from_token.clone(),
-                in_amount,
-                swaped_amount,
-                recipient_address: recipient_address.clone(),
-                dest_chain: dest_chain.clone(),
-            },
-        );
+fn read_activity(env: &Env) -> Activity {
+    let today = env.ledger().timestamp() / 86_400;
+    let activity: Option<Activity> = env.storage().instance().get(&DataKey::Activity);
+    match activity {
+        Some(activity) if activity.day == today => activity,
+        Some(activity) => Activity {
+            day: today,
+            locks_today: 0,
+            releases_today: 0,
+            ..activity
+        },
+        None => Activity {
+            last_lock_ledger: 0,
+            last_release_ledger: 0,
+            day: today,
+            locks_today: 0,
+            releases_today: 0,
+        },
+    }
+}
 
-        // Perform token transfer (interaction with external contract)
-        token::Client::new(&env, &from_token)
-            .transfer(&user_address, &env.current_contract_address(), &in_amount);
-        
-        // Transfer fee to admin
-        let admin_data: AdminData = env.storage().instance().get(&DataKey::Admin).unwrap();
-        token::Client::new(&env, &from_token)
-            .transfer(&env.current_contract_address(), &admin_data.admin_address, &swaped_amount);
+fn record_activity(env: &Env, release: bool) {
+    let mut activity = read_activity(env);
+    if release {
+        activity.last_release_ledger = env.ledger().sequence();
+        activity.releases_today += 1;
+    } else {
+        activity.last_lock_ledger = env.ledger().sequence();
+        activity.locks_today += 1;
+    }
+    env.storage().instance().set(&DataKey::Activity, &activity);
+}
 
-        // Publish lock event
-        let topics = (
-            "LockEvent",
-            user_address.clone(),
-            dest_token.clone(),
-            in_amount,
-            swaped_amount,
-        );
-        env.events().publish(
-            topics,
-            LockData {
-                user_address,
-                dest_token,
-                from_token,
-                in_amount,
-                swaped_amount,
-                recipient_address,
-                dest_chain,
-            },
+fn next_release_id(env: &Env) -> u64 {
+    let release_id: u64 = env
+        .storage()
+        .instance()
+        .get(&DataKey::ReleaseCounter)
+        .unwrap_or(0);
+    env.storage()
+        .instance()
+        .set(&DataKey::ReleaseCounter, &(release_id + 1));
+    release_id
+}
+
+fn record_release(
+    env: &Env,
+    release_id: u64,
+    lock_id: u64,
+    recipient: &Address,
+    token: &Address,
+    amount: i128,
+    source: &Address,
+) {
+    env.storage().instance().set(
+        &DataKey::Release(release_id),
+        &ReleaseRecord {
+            lock_id,
+            recipient: recipient.clone(),
+            token: token.clone(),
+            amount,
+            source: source.clone(),
+            ledger: env.ledger().sequence(),
+            timestamp: env.ledger().timestamp(),
+            reversed: false,
+        },
+    );
+}
+
+// The funds have already moved by the time the callback runs, so a failing
+// callback is reported through an event instead of reverting the release.
+fn notify_release_callback(
+    env: &Env,
+    release_id: u64,
+    user: &Address,
+    token: &Address,
+    amount: i128,
+) {
+    let callback: Option<Address> = env.storage().instance().get(&DataKey::ReleaseCallback);
+    if let Some(callback) = callback {
+        let args: Vec<Val> = (release_id, user.clone(), token.clone(), amount).into_val(env);
+        let result = env.try_invoke_contract::<(), InvokeError>(
+            &callback,
+            &Symbol::new(env, "on_release"),
+            args,
         );
+        if result.is_err() {
+            let topics = ("ReleaseCallbackFailedEvent", release_id);
+            publish_event(env, topics, callback);
+        }
+    }
+}
 
-        // Clear re-entrancy guard
-        clear_reentrancy_guard(&env);
+// Same-chain releases into a registered receiver contract are announced through
+// its `on_receive`; unlike the release callback, a rejection reverts the release.
+fn notify_receiver(env: &Env, user: &Address, token: &Address, amount: i128) {
+    if !env
+        .storage()
+        .instance()
+        .has(&DataKey::ReceiverContract(user.clone()))
+    {
+        return;
     }
 
-    pub fn release(env: Env, amount: i128, user: Address, destination_token: Address) {
-        // Check if contract is paused before proceeding
-        check_if_paused(&env);
+    let args: Vec<Val> = (token.clone(), amount).into_val(env);
+    let accepted: bool = env.invoke_contract(user, &Symbol::new(env, "on_receive"), args);
+    if !accepted {
+        panic_with_error!(env, LockError::ReceiverRejected);
+    }
+}
 
-        // Set re-entrancy guard
-        check_and_set_reentrancy_guard(&env);
-        
-        // Admin authorization
-        let admin_data: AdminData = env.storage().instance().get(&DataKey::Admin).unwrap();
-        admin_data.admin_address.require_auth();
-        
-        // Check admin balance
-        let admin_balance = token::Client::new(&env, &destination_token).balance(&admin_data.admin_address);
-        if admin_balance < amount {
-            env.panic_with_error(Error::from_type_and_code(
-                ScErrorType::Contract,
-                ScErrorCode::InvalidAction,
-            ));
-        }
+fn read_admin_reserve(env: &Env, token: &Address) -> i128 {
+    env.storage()
+        .instance()
+        .get(&DataKey::AdminReserve(token.clone()))
+        .unwrap_or(0)
+}
+
+// Warns once a capped counter crosses `cap_warning_percent` of its cap, so
+// operators can react before callers start hitting the hard limit
+fn check_cap_warning(env: &Env, cap_name: &str, value: i128, cap: i128) {
+    let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+    if config.cap_warning_percent > 0
+        && cap > 0
+        && value * 100 > cap * i128::from(config.cap_warning_percent)
+    {
+        let topics = ("CapWarningEvent", Symbol::new(env, cap_name));
+        publish_event(env, topics, (value, cap));
+    }
+}
+
+// Adds `amount` to the recipient's lifetime total, enforcing the configured cap
+fn record_recipient_release(env: &Env, recipient: &Address, amount: i128) {
+    let released = check_recipient_cap(env, recipient, amount);
+    let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+    check_cap_warning(env, "recipient_lifetime", released, config.recipient_lifetime_cap);
+    env.storage()
+        .instance()
+        .set(&DataKey::RecipientReleased(recipient.clone()), &released);
+}
+
+fn check_recipient_not_blocked(env: &Env, recipient: &Address) {
+    if env
+        .storage()
+        .instance()
+        .has(&DataKey::BlockedRecipient(recipient.clone()))
+    {
+        panic_with_error!(env, LockError::RecipientBlocked);
+    }
+}
+
+// Returns the recipient's lifetime total including `amount`, panicking past the cap
+fn check_recipient_cap(env: &Env, recipient: &Address, amount: i128) -> i128 {
+    let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+    let released: i128 = env
+        .storage()
+        .instance()
+        .get(&DataKey::RecipientReleased(recipient.clone()))
+        .unwrap_or(0)
+        + amount;
+    if config.recipient_lifetime_cap > 0 && released > config.recipient_lifetime_cap {
+        panic_with_error!(env, LockError::RecipientCapExceeded);
+    }
+    released
+}
+
+fn read_admins(env: &Env) -> Vec<Address> {
+    env.storage()
+        .instance()
+        .get(&DataKey::Admins)
+        .unwrap_or(Vec::new(env))
+}
+
+fn read_chain_admin_chains(env: &Env) -> Vec<Bytes> {
+    env.storage()
+        .instance()
+        .get(&ExtraKey::ChainAdminChains)
+        .unwrap_or(Vec::new(env))
+}
+
+// Whether `address` may release funds, either as a registered admin or as
+// the admin of some chain
+fn holds_admin_role(env: &Env, address: &Address) -> bool {
+    read_admins(env).contains(address)
+        || read_chain_admin_chains(env).iter().any(|dest_chain| {
+            env.storage()
+                .instance()
+                .get::<_, Address>(&DataKey::ChainAdmin(dest_chain))
+                .as_ref()
+                == Some(address)
+        })
+}
+
+// Locks must mature before any release unless the owner waived the wait
+fn check_lock_age(env: &Env, lock_id: u64, lock_data: &LockData) {
+    let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+    if env.ledger().sequence() - lock_data.created_ledger < config.min_lock_age_ledgers
+        && !env.storage().instance().has(&ExtraKey::LockAgeWaived(lock_id))
+    {
+        panic_with_error!(env, ExtraError::LockTooYoung);
+    }
+}
+
+// A chain with an admin of its own is released by that admin alone; other
+// chains fall back to the primary admin.
+fn read_chain_admin(env: &Env, dest_chain: &Bytes) -> Address {
+    if let Some(admin) = env
+        .storage()
+        .instance()
+        .get(&DataKey::ChainAdmin(dest_chain.clone()))
+    {
+        return admin;
+    }
+    match env.storage().instance().get::<_, AdminData>(&DataKey::Admin) {
+        Some(admin_data) => admin_data.admin_address,
+        None => panic_with_error!(env, LockError::AdminNotSet),
+    }
+}
+
+// Every check `release` performs before moving funds, shared with `dry_run_release`.
+// Releases are paid out of the releasing admin's own balance and may not pay
+// out more than what is left of the lock's `swaped_amount`. Returns the admin's
+// current balance of `token` and what the lock still owes after this release.
+fn validate_release(
+    env: &Env,
+    admin: &Address,
+    lock_id: u64,
+    amount: i128,
+    user: &Address,
+    token: &Address,
+) -> (i128, i128) {
+    let admins = read_admins(env);
+    if admins.is_empty() {
+        panic_with_error!(env, LockError::AdminNotSet);
+    }
+    let chain_admin: Option<Address> = env
+        .storage()
+        .instance()
+        .get(&DataKey::ChainAdmin(read_lock(env, lock_id).dest_chain));
+    match chain_admin {
+        Some(chain_admin) if chain_admin != *admin => panic_with_error!(env, LockError::NotAdmin),
+        Some(_) => {}
+        None if !admins.contains(admin) => panic_with_error!(env, LockError::NotAdmin),
+        None => {}
+    }
+
+    // Check admin balance, keeping the configured reserve untouched
+    let admin_balance = token::Client::new(env, token).balance(admin);
+    if admin_balance - read_admin_reserve(env, token) < amount {
+        panic_with_error!(env, LockError::InsufficientAdminBalance);
+    }
+
+    let lock_data = read_lock(env, lock_id);
+    check_releasable(env, lock_id);
+    let released_amount = read_released_amount(env, lock_id);
+    if amount <= 0 || amount > lock_data.swaped_amount - released_amount {
+        panic_with_error!(env, LockError::InvalidAmount);
+    }
+
+    check_lock_age(env, lock_id, &lock_data);
+    let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+
+    // Optionally pin releases to the token mapped to the lock's `dest_token`
+    if config.enforce_dest_token_match {
+        let mapped: Option<Address> = env
+            .storage()
+            .instance()
+            .get(&ExtraKey::DestTokenAddress(lock_data.dest_token));
+        if mapped.as_ref() != Some(token) {
+            panic_with_error!(env, ExtraError::DestTokenMismatch);
+        }
+    }
+
+    check_recipient_not_blocked(env, user);
+    check_recipient_cap(env, user, amount);
+
+    check_release_cap(env, token, amount);
+
+    (admin_balance, lock_data.swaped_amount - released_amount - amount)
+}
+
+// Pays a releasable lock's outstanding amount to its recipient out of custody
+// and credits the submitting `relayer` its share of the lock's fee. Callers
+// have already checked the release is authorized.
+fn settle_from_custody(env: &Env, lock_id: u64, relayer: &Address) {
+    let lock_data = read_lock(env, lock_id);
+    check_releasable(env, lock_id);
+    check_lock_age(env, lock_id, &lock_data);
+
+    // Check custody liquidity
+    let token_client = token::Client::new(env, &lock_data.from_token);
+    let custodian = read_custodian(env, &lock_data.from_token);
+    if custodian != env.current_contract_address() {
+        custodian.require_auth();
+    }
+    // Only what earlier partial releases left outstanding is paid
+    let amount = lock_data.swaped_amount - read_released_amount(env, lock_id);
+    if available_liquidity(env, &lock_data.from_token, &custodian, lock_id) < amount {
+        panic_with_error!(env, LockError::InsufficientLiquidity);
+    }
+
+    let recipient = Address::from_string(&lock_data.recipient_address);
+    // Neither the oracle proof nor a relayer carries the approval a stricter
+    // chain scheme asks for
+    check_release_scheme(
+        env,
+        lock_id,
+        amount,
+        &recipient,
+        &lock_data.from_token,
+        &ReleaseApproval::AdminOnly,
+    );
+    check_recipient_not_blocked(env, &recipient);
+    check_release_cap(env, &lock_data.from_token, amount);
+    record_recipient_release(env, &recipient, amount);
+    add_released_today(env, &lock_data.from_token, amount);
+    env.storage()
+        .instance()
+        .set(&DataKey::ReleasedAmount(lock_id), &lock_data.swaped_amount);
+    complete_release(env, lock_id, &recipient, lock_data.swaped_amount);
+    let release_id = next_release_id(env);
+    record_release(
+        env,
+        release_id,
+        lock_id,
+        &recipient,
+        &lock_data.from_token,
+        amount,
+        &custodian,
+    );
+    record_activity(env, true);
+
+    // Reimburse the relayer out of the fee this lock accrued. A forwarded fee
+    // never accrued, and the carried remainder is not fee.
+    let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+    let accrued_fees = read_accrued_fees(env, &lock_data.from_token);
+    let fee = match lock_data.fee_payee {
+        Some(_) => 0,
+        None => lock_data.in_amount - lock_data.swaped_amount - lock_data.remainder_charged,
+    };
+    let reimbursement = (fee * config.relayer_reimbursement_bps / 10_000).min(accrued_fees);
+    if reimbursement > 0 {
+        write_accrued_fees(env, &lock_data.from_token, accrued_fees - reimbursement);
+        let key = DataKey::RelayerAccrued(relayer.clone(), lock_data.from_token.clone());
+        let accrued: i128 = env.storage().instance().get(&key).unwrap_or(0);
+        env.storage().instance().set(&key, &(accrued + reimbursement));
+        let owed = read_relayer_owed(env, &lock_data.from_token);
+        write_relayer_owed(env, &lock_data.from_token, owed + reimbursement);
+    }
+
+    // Perform token transfer to the recipient
+    token_client.transfer(&custodian, &recipient, &amount);
+
+    // Publish release event
+    let topics = (
+        "ReleaseEvent",
+        recipient.clone(),
+        lock_data.from_token.clone(),
+        amount,
+    );
+    publish_event(env, topics, (release_id, lock_id));
+
+    notify_release_callback(env, release_id, &recipient, &lock_data.from_token, amount);
+}
+
+fn read_release_scheme(env: &Env, dest_chain: &Bytes) -> ReleaseScheme {
+    read_supported_chains(env)
+        .get(dest_chain.clone())
+        .unwrap_or(ReleaseScheme::SingleAdmin)
+}
+
+// Enforces the release scheme of the lock's destination chain on top of the
+// admin's own authorization. Unregistered chains only need the admin. Paths
+// without a way to carry approvals pass `AdminOnly`, so they only serve
+// `SingleAdmin` chains.
+fn check_release_scheme(
+    env: &Env,
+    lock_id: u64,
+    amount: i128,
+    user: &Address,
+    token: &Address,
+    approval: &ReleaseApproval,
+) {
+    let scheme = read_release_scheme(env, &read_lock(env, lock_id).dest_chain);
+    match (scheme, approval) {
+        (ReleaseScheme::SingleAdmin, _) => {}
+        (ReleaseScheme::Multisig(threshold, signers), ReleaseApproval::Signers(approvers)) => {
+            let mut approved = Vec::new(env);
+            for approver in approvers.iter() {
+                if !signers.contains(&approver) || approved.contains(&approver) {
+                    panic_with_error!(env, ExtraError::ReleaseNotAuthorized);
+                }
+                approver.require_auth();
+                approved.push_back(approver);
+            }
+            if approved.len() < threshold {
+                panic_with_error!(env, ExtraError::ReleaseNotAuthorized);
+            }
+        }
+        (ReleaseScheme::OracleSigned(public_key), ReleaseApproval::OracleSignature(signature)) => {
+            let message = (
+                env.current_contract_address(),
+                lock_id,
+                read_released_amount(env, lock_id),
+                amount,
+                user.clone(),
+                token.clone(),
+            )
+                .to_xdr(env);
+            env.crypto().ed25519_verify(&public_key, &message, signature);
+        }
+        _ => panic_with_error!(env, ExtraError::ReleaseNotAuthorized),
+    }
+}
+
+// Pays one validated release out of `admin`'s balance. The caller has
+// already checked the admin's authorization.
+fn execute_release(
+    env: &Env,
+    admin: &Address,
+    lock_id: u64,
+    amount: i128,
+    user: &Address,
+    destination_token: &Address,
+    approval: &ReleaseApproval,
+) {
+    let (admin_balance, outstanding) =
+        validate_release(env, admin, lock_id, amount, user, destination_token);
+    check_release_scheme(env, lock_id, amount, user, destination_token, approval);
+
+    // Perform token transfer to the user
+    record_recipient_release(env, user, amount);
+    add_released_today(env, destination_token, amount);
+    let released_amount = read_released_amount(env, lock_id) + amount;
+    env.storage()
+        .instance()
+        .set(&DataKey::ReleasedAmount(lock_id), &released_amount);
+    if outstanding == 0 {
+        complete_release(env, lock_id, user, released_amount);
+    }
+    let release_id = next_release_id(env);
+    record_release(env, release_id, lock_id, user, destination_token, amount, admin);
+    record_activity(env, true);
+    token::Client::new(env, destination_token).transfer(admin, user, &amount);
+    notify_receiver(env, user, destination_token, amount);
+
+    // Publish release event
+    let topics = ("ReleaseEvent", user.clone(), destination_token.clone(), amount);
+    publish_event(env, topics, (release_id, lock_id));
+
+    // Alert funding bots when admin liquidity drops below the token's threshold
+    let low_balance_threshold = read_token_config(env, destination_token).low_balance_threshold;
+    let remaining_balance = admin_balance - amount;
+    if remaining_balance < low_balance_threshold {
+        let topics = ("LowBalanceEvent", destination_token.clone());
+        publish_event(env, topics, (admin.clone(), remaining_balance));
+    }
+
+    notify_release_callback(env, release_id, user, destination_token, amount);
+}
+
+fn read_daily_volume(env: &Env, token: &Address) -> i128 {
+    let daily_volume: Option<DailyVolume> = env
+        .storage()
+        .instance()
+        .get(&DataKey::DailyVolume(token.clone()));
+    match daily_volume {
+        Some(daily_volume) if daily_volume.day == env.ledger().timestamp() / 86_400 => {
+            daily_volume.volume
+        }
+        _ => 0,
+    }
+}
+
+fn add_daily_volume(env: &Env, token: &Address, amount: i128) {
+    let volume = read_daily_volume(env, token) + amount;
+    env.storage().instance().set(
+        &DataKey::DailyVolume(token.clone()),
+        &DailyVolume {
+            day: env.ledger().timestamp() / 86_400,
+            volume,
+        },
+    );
+}
+
+fn read_release_cap(env: &Env, token: &Address) -> i128 {
+    env.storage()
+        .instance()
+        .get(&ExtraKey::ReleaseCap(token.clone()))
+        .unwrap_or(0)
+}
+
+// Releases of `token` in the current UTC day, reset once a new day starts
+fn read_released_today(env: &Env, token: &Address) -> i128 {
+    let released_today: Option<DailyVolume> = env
+        .storage()
+        .instance()
+        .get(&ExtraKey::ReleasedToday(token.clone()));
+    match released_today {
+        Some(released_today) if released_today.day == env.ledger().timestamp() / 86_400 => {
+            released_today.volume
+        }
+        _ => 0,
+    }
+}
+
+// Bounds what every release path together may pay out of `token` per day
+fn check_release_cap(env: &Env, token: &Address, amount: i128) {
+    let release_cap = read_release_cap(env, token);
+    if release_cap > 0 && read_released_today(env, token) + amount > release_cap {
+        panic_with_error!(env, ExtraError::DailyCapExceeded);
+    }
+}
+
+fn add_released_today(env: &Env, token: &Address, amount: i128) {
+    let volume = read_released_today(env, token) + amount;
+    env.storage().instance().set(
+        &ExtraKey::ReleasedToday(token.clone()),
+        &DailyVolume {
+            day: env.ledger().timestamp() / 86_400,
+            volume,
+        },
+    );
+}
+
+fn read_accrued_fees(env: &Env, token: &Address) -> i128 {
+    env.storage()
+        .instance()
+        .get(&DataKey::AccruedFees(token.clone()))
+        .unwrap_or(0)
+}
+
+fn write_accrued_fees(env: &Env, token: &Address, amount: i128) {
+    let key = DataKey::AccruedFees(token.clone());
+    if amount > 0 {
+        env.storage().instance().set(&key, &amount);
+    } else {
+        env.storage().instance().remove(&key);
+    }
+}
+
+// Total reimbursement owed to relayers in `token`, across every relayer.
+fn read_relayer_owed(env: &Env, token: &Address) -> i128 {
+    env.storage()
+        .instance()
+        .get(&ExtraKey::RelayerOwed(token.clone()))
+        .unwrap_or(0)
+}
+
+fn write_relayer_owed(env: &Env, token: &Address, amount: i128) {
+    let key = ExtraKey::RelayerOwed(token.clone());
+    if amount > 0 {
+        env.storage().instance().set(&key, &amount);
+    } else {
+        env.storage().instance().remove(&key);
+    }
+}
+
+// Principal of a held lock still in custody, reserved for its release or refund
+fn read_held_principal(env: &Env, lock_id: u64) -> i128 {
+    env.storage()
+        .instance()
+        .get(&ExtraKey::PrincipalHeld(lock_id))
+        .unwrap_or(0)
+}
+
+// Total principal held in custody for locks of `token`
+fn read_held_principal_total(env: &Env, token: &Address) -> i128 {
+    env.storage()
+        .instance()
+        .get(&ExtraKey::HeldPrincipal(token.clone()))
+        .unwrap_or(0)
+}
+
+fn write_held_principal(env: &Env, lock_id: u64, token: &Address, held: i128, total: i128) {
+    let lock_key = ExtraKey::PrincipalHeld(lock_id);
+    if held > 0 {
+        env.storage().instance().set(&lock_key, &held);
+    } else {
+        env.storage().instance().remove(&lock_key);
+    }
+    let total_key = ExtraKey::HeldPrincipal(token.clone());
+    if total > 0 {
+        env.storage().instance().set(&total_key, &total);
+    } else {
+        env.storage().instance().remove(&total_key);
+    }
+}
+
+// Frees up to `amount` of a held lock's reserved principal as it is paid out
+fn consume_held_principal(env: &Env, lock_id: u64, amount: i128) {
+    let held = read_held_principal(env, lock_id);
+    if held == 0 {
+        return;
+    }
+    let consumed = amount.min(held);
+    let token = read_lock(env, lock_id).from_token;
+    let total = read_held_principal_total(env, &token) - consumed;
+    write_held_principal(env, lock_id, &token, held - consumed, total);
+}
+
+// What the custodian holds in `token` beyond the fees, protocol remainder,
+// relayer reimbursements and held principal it keeps for others. A release
+// of a held lock may also spend that lock's own principal.
+fn available_liquidity(env: &Env, token: &Address, custodian: &Address, lock_id: u64) -> i128 {
+    let owed = read_accrued_fees(env, token)
+        + read_protocol_remainder(env, token) / FEE_SCALE
+        + read_relayer_owed(env, token)
+        + read_held_principal_total(env, token)
+        - read_held_principal(env, lock_id);
+    token::Client::new(env, token).balance(custodian) - owed
+}
+
+fn check_memo(env: &Env, memo: &Option<Bytes>) {
+    let schema: Option<MemoSchema> = env.storage().instance().get(&DataKey::MemoSchema);
+    let Some(schema) = schema else {
+        return;
+    };
+
+    let conforms = match memo {
+        Some(memo) => {
+            memo.len() >= schema.min_len
+                && (schema.max_len == 0 || memo.len() <= schema.max_len)
+                && memo.len() >= schema.prefix.len()
+                && memo.slice(0..schema.prefix.len()) == schema.prefix
+        }
+        None => false,
+    };
+    if !conforms {
+        panic_with_error!(env, LockError::InvalidMemo);
+    }
+}
+
+fn read_fee_stats(env: &Env) -> FeeStats {
+    env.storage()
+        .instance()
+        .get(&DataKey::FeeStats)
+        .unwrap_or(FeeStats {
+            total_in: 0,
+            total_fees: 0,
+        })
+}
+
+fn read_user_stats(env: &Env, user: &Address) -> UserStats {
+    env.storage()
+        .instance()
+        .get(&ExtraKey::UserStats(user.clone()))
+        .unwrap_or(UserStats {
+            total_locked: 0,
+            total_released: 0,
+            lock_count: 0,
+            fees_paid: 0,
+        })
+}
+
+fn record_user_lock(env: &Env, user: &Address, in_amount: i128, fee: i128) {
+    let mut stats = read_user_stats(env, user);
+    match (
+        stats.total_locked.checked_add(in_amount),
+        stats.fees_paid.checked_add(fee),
+    ) {
+        (Some(total_locked), Some(fees_paid)) => {
+            stats.total_locked = total_locked;
+            stats.fees_paid = fees_paid;
+        }
+        _ => panic_with_error!(env, LockError::AmountOverflow),
+    }
+    // A user's first lock makes them a new unique user
+    if stats.lock_count == 0 {
+        let unique_users: u64 = env
+            .storage()
+            .instance()
+            .get(&ExtraKey::UniqueUsers)
+            .unwrap_or(0);
+        env.storage()
+            .instance()
+            .set(&ExtraKey::UniqueUsers, &(unique_users + 1));
+    }
+    stats.lock_count += 1;
+    env.storage()
+        .instance()
+        .set(&ExtraKey::UserStats(user.clone()), &stats);
+}
+
+fn read_user_locks(env: &Env, user: &Address) -> Vec<u64> {
+    env.storage()
+        .instance()
+        .get(&ExtraKey::UserLocks(user.clone()))
+        .unwrap_or(Vec::new(env))
+}
+
+fn append_user_lock(env: &Env, user: &Address, lock_id: u64) {
+    let mut lock_ids = read_user_locks(env, user);
+    lock_ids.push_back(lock_id);
+    env.storage()
+        .instance()
+        .set(&ExtraKey::UserLocks(user.clone()), &lock_ids);
+}
+
+// Remembers every token that has ever been locked
+fn record_known_token(env: &Env, token: &Address) {
+    if env
+        .storage()
+        .instance()
+        .has(&DataKey::KnownToken(token.clone()))
+    {
+        return;
+    }
+    let mut known_tokens: Vec<Address> = env
+        .storage()
+        .instance()
+        .get(&DataKey::TokenRegistry)
+        .unwrap_or(Vec::new(env));
+    known_tokens.push_back(token.clone());
+    env.storage()
+        .instance()
+        .set(&DataKey::TokenRegistry, &known_tokens);
+    env.storage()
+        .instance()
+        .set(&DataKey::KnownToken(token.clone()), &());
+}
+
+fn record_fee_stats(env: &Env, in_amount: i128, fee: i128) {
+    let mut stats = read_fee_stats(env);
+    match (
+        stats.total_in.checked_add(in_amount),
+        stats.total_fees.checked_add(fee),
+    ) {
+        (Some(total_in), Some(total_fees)) => {
+            stats.total_in = total_in;
+            stats.total_fees = total_fees;
+        }
+        _ => panic_with_error!(env, LockError::AmountOverflow),
+    }
+    env.storage().instance().set(&DataKey::FeeStats, &stats);
+}
+
+// The fee rate a lock of `token` pays right now, in hundredths of a basis
+// point. Token overrides win, then the volume-based mode, then the global fee.
+fn effective_fee_scaled(env: &Env, config: &Config, token_config: &TokenConfig, token: &Address) -> i128 {
+    if let Some(fee_scaled) = token_config.fee_scaled {
+        return fee_scaled;
+    }
+    let dynamic_fee: Option<DynamicFee> = env.storage().instance().get(&DataKey::DynamicFee);
+    match dynamic_fee {
+        Some(dynamic_fee) => {
+            let volume = read_daily_volume(env, token).min(dynamic_fee.target_daily_volume);
+            let bps_scale = FEE_SCALE / 10_000;
+            dynamic_fee.min_fee_bps * bps_scale
+                + (dynamic_fee.max_fee_bps - dynamic_fee.min_fee_bps) * bps_scale * volume
+                    / dynamic_fee.target_daily_volume
+        }
+        None => config.fee_scaled,
+    }
+}
+
+fn read_protocol_remainder(env: &Env, token: &Address) -> i128 {
+    env.storage()
+        .instance()
+        .get(&DataKey::ProtocolRemainder(token.clone()))
+        .unwrap_or(0)
+}
+
+// Fees round down, so each lock leaves a fraction of a unit behind. Those
+// fractions build up per token in `FEE_SCALE` units; returns the whole units
+// that adding this lock's fraction completes, along with the new total.
+fn carry_remainder(env: &Env, token: &Address, in_amount: i128, fee_scaled: i128) -> (i128, i128) {
+    let fraction = match in_amount.checked_mul(fee_scaled) {
+        Some(scaled_fee) => scaled_fee % FEE_SCALE,
+        None => panic_with_error!(env, LockError::AmountOverflow),
+    };
+    let before = read_protocol_remainder(env, token);
+    let after = before + fraction;
+    (after / FEE_SCALE - before / FEE_SCALE, after)
+}
+
+fn compute_fee(env: &Env, in_amount: i128, fee_scaled: i128) -> i128 {
+    match in_amount.checked_mul(fee_scaled) {
+        Some(scaled_fee) => scaled_fee / FEE_SCALE,
+        None => panic_with_error!(env, LockError::AmountOverflow),
+    }
+}
+
+fn check_batch_size(env: &Env, len: u32) {
+    let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+    if len > config.max_batch_size {
+        panic_with_error!(env, LockError::BatchTooLarge);
+    }
+}
+
+// First ledger at which the admin may refund a lock; its creation ledger
+// unless the user extended the grace period
+fn read_refund_grace(env: &Env, lock_id: u64, lock_data: &LockData) -> u32 {
+    env.storage()
+        .instance()
+        .get(&ExtraKey::RefundGrace(lock_id))
+        .unwrap_or(lock_data.created_ledger)
+}
+
+fn in_refund_grace(env: &Env, lock_id: u64) -> bool {
+    env.ledger().sequence() < read_refund_grace(env, lock_id, &read_lock(env, lock_id))
+}
+
+// Returns a lock's `in_amount` to its user, or to the refund fallback if the
+// user cannot receive it, keeping `refund_fee_bps` of it as a fee.
+fn refund_lock(env: &Env, lock_id: u64, admin: &Address, refund_fee_bps: i128) {
+    let lock_data = read_lock(env, lock_id);
+    match read_lock_status(env, lock_id) {
+        LockStatus::Released => panic_with_error!(env, LockError::AlreadyReleased),
+        status if !is_unsettled(status) => panic_with_error!(env, LockError::InvalidLockStatus),
+        _ => {}
+    }
+    // Part of the swapped amount already reached the recipient, so refunding
+    // the full `in_amount` would pay the lock out twice
+    if read_released_amount(env, lock_id) > 0 {
+        panic_with_error!(env, ExtraError::PartiallyReleased);
+    }
+    write_lock_status(env, lock_id, LockStatus::Refunded);
+
+    // The fee and carried remainder go back to the user, so they are no
+    // longer owed to the fee recipient or the protocol. A fee forwarded at
+    // lock time never accrued and is reclaimed from its recipient instead.
+    let retained = lock_data.in_amount - lock_data.swaped_amount;
+    let fee = retained - lock_data.remainder_charged;
+    let contract_address = env.current_contract_address();
+    let custodian = read_custodian(env, &lock_data.from_token);
+    if lock_data.fee_payee.is_none() {
+        let accrued = read_accrued_fees(env, &lock_data.from_token);
+        write_accrued_fees(env, &lock_data.from_token, accrued - fee.min(accrued));
+    }
+    if lock_data.remainder_charged > 0 {
+        env.storage().instance().set(
+            &DataKey::ProtocolRemainder(lock_data.from_token.clone()),
+            &(read_protocol_remainder(env, &lock_data.from_token)
+                - lock_data.remainder_charged * FEE_SCALE),
+        );
+    }
+
+    // Pull the swapped portion back next to the retained fee, unless custody
+    // already holds it
+    let token_client = token::Client::new(env, &lock_data.from_token);
+    let principal_held = read_held_principal(env, lock_id) > 0;
+    consume_held_principal(env, lock_id, lock_data.swaped_amount);
+    if !principal_held {
+        token_client.transfer(admin, &contract_address, &lock_data.swaped_amount);
+    }
+    let held = match &lock_data.fee_payee {
+        Some(fee_payee) => {
+            if fee > 0 {
+                if fee_payee != admin {
+                    fee_payee.require_auth();
+                }
+                token_client.transfer(fee_payee, &contract_address, &fee);
+            }
+            lock_data.remainder_charged
+        }
+        None => retained,
+    };
+    let held = if principal_held {
+        held + lock_data.swaped_amount
+    } else {
+        held
+    };
+    if held > 0 && custodian != contract_address {
+        pay_from_custody(env, &lock_data.from_token, &contract_address, held);
+    }
+
+    // A cancellation fee is kept back and owed to the fee recipient
+    let refund_fee = match lock_data.in_amount.checked_mul(refund_fee_bps) {
+        Some(scaled_fee) => scaled_fee / 10_000,
+        None => panic_with_error!(env, LockError::AmountOverflow),
+    };
+    let refund_amount = lock_data.in_amount - refund_fee;
+    if refund_fee > 0 {
+        let custodian = read_custodian(env, &lock_data.from_token);
+        if custodian != contract_address {
+            token_client.transfer(&contract_address, &custodian, &refund_fee);
+        }
+        let accrued = read_accrued_fees(env, &lock_data.from_token);
+        write_accrued_fees(env, &lock_data.from_token, accrued + refund_fee);
+
+        let topics = ("RefundFeeEvent", lock_id);
+        publish_event(env, topics, refund_fee);
+    }
+
+    let refunded_to = if matches!(
+        token_client.try_transfer(&contract_address, &lock_data.user_address, &refund_amount),
+        Ok(Ok(()))
+    ) {
+        lock_data.user_address
+    } else {
+        match lock_data.refund_fallback {
+            Some(fallback) => {
+                token_client.transfer(&contract_address, &fallback, &refund_amount);
+                fallback
+            }
+            None => panic_with_error!(env, LockError::RefundFailed),
+        }
+    };
+
+    record_settlement(env, lock_id, &refunded_to, refund_amount);
+
+    let topics = ("RefundEvent", lock_id, refunded_to);
+    publish_event(env, topics, refund_amount);
+}
+
+// Where a fee worth forwarding is sent first: the fee recipient, or the admin
+// while none is set. None when the fee is dust and stays in custody.
+fn planned_fee_payee(env: &Env, config: &Config, fee: i128) -> Option<Address> {
+    if config.forward_dust_threshold < 1 || fee < config.forward_dust_threshold {
+        return None;
+    }
+    env.storage()
+        .instance()
+        .get(&DataKey::FeeRecipient)
+        .or_else(|| {
+            env.storage()
+                .instance()
+                .get::<_, AdminData>(&DataKey::Admin)
+                .map(|admin_data| admin_data.admin_address)
+        })
+}
+
+fn read_fee_recipient_fallbacks(env: &Env) -> Vec<Address> {
+    env.storage()
+        .instance()
+        .get(&ExtraKey::FeeRecipientFallbacks)
+        .unwrap_or(Vec::new(env))
+}
+
+// Sends `fee` to `payee`, moving down the fallback list while transfers fail.
+// Returns whoever accepted it, or None if nobody could.
+fn forward_fee(
+    env: &Env,
+    token_client: &token::Client,
+    from: &Address,
+    payee: Address,
+    fee: i128,
+) -> Option<Address> {
+    let mut candidates = Vec::from_array(env, [payee]);
+    candidates.append(&read_fee_recipient_fallbacks(env));
+    candidates
+        .iter()
+        .find(|candidate| matches!(token_client.try_transfer(from, candidate, &fee), Ok(Ok(()))))
+}
+
+fn check_lock_bounds(env: &Env, min_lock: i128, max_lock: i128) {
+    if min_lock < 1 || (max_lock != 0 && max_lock < min_lock) {
+        panic_with_error!(env, LockError::InvalidConfig);
+    }
+}
+
+fn read_pause_history(env: &Env) -> Vec<PauseRecord> {
+    env.storage()
+        .instance()
+        .get(&ExtraKey::PauseHistory)
+        .unwrap_or(Vec::new(env))
+}
+
+fn record_pause(env: &Env, paused: bool, actor: &Address) {
+    let mut history = read_pause_history(env);
+    if history.len() >= MAX_PAUSE_HISTORY {
+        history.pop_front();
+    }
+    history.push_back(PauseRecord {
+        paused,
+        ledger: env.ledger().sequence(),
+        actor: actor.clone(),
+    });
+    env.storage().instance().set(&ExtraKey::PauseHistory, &history);
+}
+
+// Role assignments may be made by the owner or by the role manager it
+// delegated them to
+fn require_role_admin(env: &Env, caller: &Address) {
+    let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+    let role_manager: Option<Address> = env.storage().instance().get(&ExtraKey::RoleManager);
+    if *caller != owner && role_manager.as_ref() != Some(caller) {
+        panic_with_error!(env, ExtraError::NotRoleAdmin);
+    }
+    caller.require_auth();
+}
+
+fn check_if_paused(env: &Env) {
+    if env.storage().instance().has(&DataKey::Paused) {
+        panic_with_error!(env, LockError::ContractPaused);
+    }
+}
+
+#[contractimpl]
+impl LockAndReleaseContract {
+    /// `fee_percentage` is a whole percentage and is stored in the finer
+    /// `fee_scaled` units; use `set_fee_scaled` for sub-percent fees. A non-zero
+    /// `sunset_ledger` stops new locks from that ledger on. Locks must be
+    /// within `[min_lock, max_lock]`, where a `max_lock` of 0 means no cap.
+    pub fn initialize(
+        env: Env,
+        owner: Address,
+        fee_percentage: i128,
+        sunset_ledger: u32,
+        min_lock: i128,
+        max_lock: i128,
+    ) {
+        if env.storage().instance().has(&DataKey::Init) {
+            panic_with_error!(&env, LockError::AlreadyInitialized);
+        }
+        if !(0..=100).contains(&fee_percentage) {
+            panic_with_error!(&env, LockError::InvalidFee);
+        }
+        check_lock_bounds(&env, min_lock, max_lock);
+
+        env.storage().instance().set(&DataKey::Owner, &owner);
+        let config = Config {
+            fee_scaled: fee_percentage * (FEE_SCALE / 100),
+            dup_window_ledgers: 0,
+            min_confirmations: 0,
+            recipient_lifetime_cap: 0,
+            relayer_reimbursement_bps: 0,
+            max_pending_per_user: 0,
+            require_role_separation: false,
+            cap_warning_percent: 0,
+            high_value_threshold: 0,
+            forward_dust_threshold: 0,
+            sunset_ledger,
+            validate_dest_token: false,
+            dispute_window_ledgers: 0,
+            max_batch_size: MAX_QUERY_LEN,
+            refund_fee_bps: 0,
+            enforce_dest_token_match: false,
+            min_lock,
+            max_lock,
+            min_lock_age_ledgers: 0,
+            hold_principal: false,
+        };
+        env.storage().instance().set(&DataKey::Config, &config);
+        env.storage().instance().set(&DataKey::Init, &());
+
+        let topics = ("InitializeEvent", owner);
+        publish_event(&env, topics, config);
+    }
+
+    /// Registers another admin. The first admin registered is the primary one:
+    /// it receives locked funds (unless `set_hold_principal` keeps them in
+    /// custody) and cosigns refunds and status changes, while
+    /// any registered admin may `release` from its own balance. `caller` is the
+    /// owner or the role manager, as for every role assignment.
+    pub fn add_admin(env: Env, caller: Address, admin: Address) {
+        check_if_paused(&env);
+        require_role_admin(&env, &caller);
+
+        let mut admins = read_admins(&env);
+        if admins.contains(&admin) {
+            panic_with_error!(&env, LockError::AdminAlreadySet);
+        }
+
+        // Separation of duties: the owner key may not also act as admin
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        if config.require_role_separation && admin == owner {
+            panic_with_error!(&env, LockError::RoleSeparationViolated);
+        }
+
+        admins.push_back(admin.clone());
+        env.storage().instance().set(&DataKey::Admins, &admins);
+        if !env.storage().instance().has(&DataKey::Admin) {
+            env.storage().instance().set(
+                &DataKey::Admin,
+                &AdminData {
+                    admin_address: admin.clone(),
+                },
+            );
+        }
+
+        let topics = ("AdminAddedEvent", admin.clone());
+        publish_event(&env, topics, AdminData { admin_address: admin });
+    }
+
+    /// Delegates role assignments (admins, chain admins, the fee recipient and
+    /// its fallbacks, the proof verifier) to `role_manager` alongside the
+    /// owner. None withdraws the delegation.
+    pub fn set_role_manager(env: Env, role_manager: Option<Address>) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        match &role_manager {
+            Some(role_manager) => env
+                .storage()
+                .instance()
+                .set(&ExtraKey::RoleManager, role_manager),
+            None => env.storage().instance().remove(&ExtraKey::RoleManager),
+        }
+
+        let topics = ("RoleManagerUpdatedEvent", ());
+        publish_event(&env, topics, role_manager);
+    }
+
+    pub fn get_role_manager(env: Env) -> Option<Address> {
+        env.storage().instance().get(&ExtraKey::RoleManager)
+    }
+
+    pub fn set_require_role_separation(env: Env, require_role_separation: bool) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        // Refuse to turn the policy on while it is already being violated
+        if require_role_separation && holds_admin_role(&env, &owner) {
+            panic_with_error!(&env, LockError::RoleSeparationViolated);
+        }
+
+        let mut config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.require_role_separation = require_role_separation;
+        env.storage().instance().set(&DataKey::Config, &config);
+
+        let topics = ("RoleSeparationUpdatedEvent", ());
+        publish_event(&env, topics, require_role_separation);
+    }
+
+    pub fn set_cap_warning_percent(env: Env, cap_warning_percent: u32) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        if cap_warning_percent > 100 {
+            panic_with_error!(&env, LockError::InvalidConfig);
+        }
+
+        let mut config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.cap_warning_percent = cap_warning_percent;
+        env.storage().instance().set(&DataKey::Config, &config);
+
+        let topics = ("CapWarningPercentUpdatedEvent", ());
+        publish_event(&env, topics, cap_warning_percent);
+    }
+
+    pub fn get_cap_warning_percent(env: Env) -> u32 {
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.cap_warning_percent
+    }
+
+    pub fn get_require_role_separation(env: Env) -> bool {
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.require_role_separation
+    }
+
+    /// Deregisters `admin`. Removing the primary admin hands that role to the
+    /// longest-registered remaining one.
+    pub fn remove_admin(env: Env, caller: Address, admin: Address) {
+        check_if_paused(&env);
+        require_role_admin(&env, &caller);
+
+        let mut admins = read_admins(&env);
+        let index = match admins.first_index_of(&admin) {
+            Some(index) => index,
+            None => panic_with_error!(&env, LockError::AdminNotSet),
+        };
+        admins.remove(index);
+        env.storage().instance().set(&DataKey::Admins, &admins);
+
+        let primary: AdminData = env.storage().instance().get(&DataKey::Admin).unwrap();
+        if primary.admin_address == admin {
+            match admins.first() {
+                Some(next) => env
+                    .storage()
+                    .instance()
+                    .set(&DataKey::Admin, &AdminData { admin_address: next }),
+                None => env.storage().instance().remove(&DataKey::Admin),
+            }
+        }
+
+        let topics = ("AdminRemovedEvent", admin);
+        publish_event(&env, topics, ());
+    }
+
+    /// Every registered admin, primary first.
+    pub fn get_admins(env: Env) -> Vec<Address> {
+        read_admins(&env)
+    }
+
+    /// False while the contract is admin-less, in which state every lock and
+    /// release reverts.
+    pub fn has_admin(env: Env) -> bool {
+        env.storage().instance().has(&DataKey::Admin)
+    }
+
+    pub fn get_config(env: Env) -> Config {
+        env.storage().instance().get(&DataKey::Config).unwrap()
+    }
+
+    /// The primary admin. None until an admin is added, so clients can check
+    /// readiness before calling `lock`.
+    pub fn get_admin(env: Env) -> Option<Address> {
+        env.storage()
+            .instance()
+            .get::<_, AdminData>(&DataKey::Admin)
+            .map(|admin_data| admin_data.admin_address)
+    }
+
+    pub fn get_owner(env: Env) -> Address {
+        env.storage().instance().get(&DataKey::Owner).unwrap()
+    }
+
+    /// Starts handing the contract to `new_owner`, who must `accept_ownership`
+    /// before gaining any owner rights. A later call replaces the pending owner.
+    pub fn transfer_ownership(env: Env, new_owner: Address) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        // Proposing an admin would only set up an acceptance that must fail
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        if config.require_role_separation && holds_admin_role(&env, &new_owner) {
+            panic_with_error!(&env, LockError::RoleSeparationViolated);
+        }
+
+        env.storage().instance().set(&DataKey::PendingOwner, &new_owner);
+
+        let topics = ("OwnershipTransferStartedEvent", owner, new_owner);
+        publish_event(&env, topics, ());
+    }
+
+    pub fn accept_ownership(env: Env) {
+        let new_owner: Address = match env.storage().instance().get(&DataKey::PendingOwner) {
+            Some(new_owner) => new_owner,
+            None => panic_with_error!(&env, LockError::NoPendingOwner),
+        };
+        new_owner.require_auth();
+
+        // Separation of duties also applies to the incoming owner
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        if config.require_role_separation && holds_admin_role(&env, &new_owner) {
+            panic_with_error!(&env, LockError::RoleSeparationViolated);
+        }
+
+        let old_owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        env.storage().instance().set(&DataKey::Owner, &new_owner);
+        env.storage().instance().remove(&DataKey::PendingOwner);
+
+        let topics = ("OwnershipTransferredEvent", old_owner, new_owner);
+        publish_event(&env, topics, ());
+    }
+
+    pub fn get_pending_owner(env: Env) -> Option<Address> {
+        env.storage().instance().get(&DataKey::PendingOwner)
+    }
+
+    /// Swaps the contract's code for the uploaded wasm `new_wasm_hash`. Storage
+    /// is kept, and the hash is recorded for `code_hash`.
+    pub fn upgrade(env: Env, new_wasm_hash: BytesN<32>) {
+        if !env.storage().instance().has(&DataKey::Init) {
+            panic_with_error!(&env, ExtraError::NotInitialized);
+        }
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        env.storage().instance().set(&DataKey::CodeHash, &new_wasm_hash);
+        env.deployer().update_current_contract_wasm(new_wasm_hash.clone());
+
+        let topics = ("UpgradeEvent", ());
+        publish_event(&env, topics, new_wasm_hash);
+    }
+
+    /// The wasm hash installed by the last `upgrade`, for comparing against an
+    /// audited build. The host does not expose a contract's own code hash, so
+    /// this is None until the contract has been upgraded once.
+    pub fn code_hash(env: Env) -> Option<BytesN<32>> {
+        env.storage().instance().get(&DataKey::CodeHash)
+    }
+
+    /// Single toggle over `pause`/`unpause`. Locks and releases fail with
+    /// `ContractPaused` while paused; getters keep working.
+    pub fn set_paused(env: Env, paused: bool) {
+        if paused {
+            Self::pause(env)
+        } else {
+            Self::unpause(env)
+        }
+    }
+
+    pub fn is_paused(env: Env) -> bool {
+        env.storage().instance().has(&DataKey::Paused)
+    }
+
+    pub fn pause(env: Env) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth(); 
+
+        if env.storage().instance().has(&DataKey::Paused) {
+            panic_with_error!(&env, LockError::AlreadyPaused);
+        }
+
+        // Set the contract state to paused
+        env.storage().instance().set(&DataKey::Paused, &());
+        record_pause(&env, true, &owner);
+
+        let topics = ("ContractPausedEvent", ());
+        publish_event(&env, topics, ());
+    }
+
+    pub fn unpause(env: Env) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth(); 
+
+        if !env.storage().instance().has(&DataKey::Paused) {
+            panic_with_error!(&env, LockError::NotPaused);
+        }
+
+        // Set the contract state to unpaused
+        env.storage().instance().remove(&DataKey::Paused);
+        record_pause(&env, false, &owner);
+
+        let topics = ("ContractUnpausedEvent", ());
+        publish_event(&env, topics, ());
+    }
+
+    /// The most recent pauses and unpauses, oldest first, capped at
+    /// `MAX_PAUSE_HISTORY` entries.
+    pub fn get_pause_history(env: Env) -> Vec<PauseRecord> {
+        read_pause_history(&env)
+    }
+
+    /// Last-resort recovery: moves the contract's entire balance of each listed
+    /// token to `to`. Only allowed while paused. Funds held by an external
+    /// custodian are not touched.
+    ///
+    /// The protocol's own accrued fees and whole rounding-remainder units leave
+    /// with the balance, so they are settled here. Principal held for locks and
+    /// relayer reimbursements are owed to others and stay reserved: once the
+    /// custody is refunded they are paid out before any other release.
+    pub fn emergency_drain(env: Env, tokens: Vec<Address>, to: Address) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        if !env.storage().instance().has(&DataKey::Paused) {
+            panic_with_error!(&env, LockError::NotPaused);
+        }
+        if tokens.len() > MAX_QUERY_LEN {
+            panic_with_error!(&env, LockError::TooManyItems);
+        }
+
+        for token in tokens.iter() {
+            let token_client = token::Client::new(&env, &token);
+            let balance = token_client.balance(&env.current_contract_address());
+            if balance < 1 {
+                continue;
+            }
+            // Fees and remainder held here leave with the rest of the balance
+            if read_custodian(&env, &token) == env.current_contract_address() {
+                write_accrued_fees(&env, &token, 0);
+                let protocol_remainder = read_protocol_remainder(&env, &token);
+                let drained = protocol_remainder / FEE_SCALE;
+                if drained > 0 {
+                    env.storage().instance().set(
+                        &DataKey::ProtocolRemainder(token.clone()),
+                        &(protocol_remainder - drained * FEE_SCALE),
+                    );
+                }
+            }
+            token_client.transfer(&env.current_contract_address(), &to, &balance);
+
+            let topics = ("EmergencyDrainEvent", token, to.clone());
+            publish_event(&env, topics, balance);
+        }
+    }
+
+    pub fn set_token_config(env: Env, token: Address, token_config: TokenConfig) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let token_config = TokenConfig {
+            decimals: token::Client::new(&env, &token).decimals(),
+            ..token_config
+        };
+
+        if let Some(fee_scaled) = token_config.fee_scaled {
+            if !(0..=FEE_SCALE).contains(&fee_scaled) {
+                panic_with_error!(&env, LockError::InvalidFee);
+            }
+        }
+        if token_config.min_swaped < 1 {
+            panic_with_error!(&env, LockError::InvalidConfig);
+        }
+
+        // A pause reason only describes the pause it was recorded with
+        if !token_config.paused {
+            env.storage()
+                .instance()
+                .remove(&DataKey::TokenPauseInfo(token.clone()));
+        }
+
+        write_token_config(&env, &token, &token_config);
+
+        let topics = ("TokenConfigUpdatedEvent", token);
+        publish_event(&env, topics, token_config);
+    }
+
+    /// Pauses locks of `token`, recording why and who paused it.
+    pub fn pause_token(env: Env, token: Address, reason: Symbol) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let mut token_config = read_token_config(&env, &token);
+        token_config.paused = true;
+        write_token_config(&env, &token, &token_config);
+        env.storage().instance().set(
+            &DataKey::TokenPauseInfo(token.clone()),
+            &(reason.clone(), owner.clone()),
+        );
+
+        let topics = ("TokenPausedEvent", token, reason);
+        publish_event(&env, topics, owner);
+    }
+
+    pub fn unpause_token(env: Env, token: Address) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let mut token_config = read_token_config(&env, &token);
+        token_config.paused = false;
+        write_token_config(&env, &token, &token_config);
+        env.storage()
+            .instance()
+            .remove(&DataKey::TokenPauseInfo(token.clone()));
+
+        let topics = ("TokenUnpausedEvent", token);
+        publish_event(&env, topics, owner);
+    }
+
+    /// Tokens whose locks are currently paused, in the order they were paused.
+    pub fn list_paused_tokens(env: Env) -> Vec<Address> {
+        read_paused_tokens(&env)
+    }
+
+    /// Why `token` was paused and by whom. None while it is not paused, or when
+    /// it was paused through `set_token_config` without a reason.
+    pub fn token_pause_info(env: Env, token: Address) -> Option<(Symbol, Address)> {
+        env.storage()
+            .instance()
+            .get(&DataKey::TokenPauseInfo(token))
+    }
+
+    /// Holds `token` at `custodian` instead of the contract: retained fees are
+    /// sent there and proof releases, refunds and fee payouts draw from it with
+    /// the custodian's authorization. Balances already held elsewhere are not
+    /// moved.
+    pub fn set_token_custodian(env: Env, token: Address, custodian: Address) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        env.storage()
+            .instance()
+            .set(&DataKey::TokenCustodian(token.clone()), &custodian);
+
+        let topics = ("TokenCustodianSetEvent", token);
+        publish_event(&env, topics, custodian);
+    }
+
+    pub fn remove_token_custodian(env: Env, token: Address) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        env.storage()
+            .instance()
+            .remove(&DataKey::TokenCustodian(token.clone()));
+
+        let topics = ("TokenCustodianRemovedEvent", token);
+        publish_event(&env, topics, ());
+    }
+
+    /// The address holding the contract's `token`, the contract itself unless a
+    /// custodian is configured.
+    pub fn get_token_custodian(env: Env, token: Address) -> Address {
+        read_custodian(&env, &token)
+    }
+
+    /// While the allow-list is empty any token may be locked.
+    pub fn add_allowed_token(env: Env, token: Address) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let mut allowed_tokens = read_allowed_tokens(&env);
+        if !push_allowed_token(&env, &mut allowed_tokens, &token) {
+            panic_with_error!(&env, LockError::TokenAlreadyAllowed);
+        }
+        env.storage()
+            .instance()
+            .set(&DataKey::AllowedTokens, &allowed_tokens);
+
+        let topics = ("AllowedTokenAddedEvent", token);
+        publish_event(&env, topics, ());
+    }
+
+    /// Adds every token not already allowed, skipping duplicates, and returns
+    /// how many were added.
+    pub fn add_allowed_tokens(env: Env, tokens: Vec<Address>) -> u32 {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        if tokens.len() > MAX_QUERY_LEN {
+            panic_with_error!(&env, LockError::TooManyItems);
+        }
+
+        let mut allowed_tokens = read_allowed_tokens(&env);
+        let mut added = 0;
+        for token in tokens.iter() {
+            if push_allowed_token(&env, &mut allowed_tokens, &token) {
+                added += 1;
+            }
+        }
+        env.storage()
+            .instance()
+            .set(&DataKey::AllowedTokens, &allowed_tokens);
+
+        let topics = ("AllowedTokensAddedEvent", ());
+        publish_event(&env, topics, added);
+
+        added
+    }
+
+    pub fn remove_allowed_token(env: Env, token: Address) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let mut allowed_tokens = read_allowed_tokens(&env);
+        match allowed_tokens.first_index_of(&token) {
+            Some(index) => {
+                allowed_tokens.remove(index);
+            }
+            None => panic_with_error!(&env, LockError::UnsupportedToken),
+        }
+        env.storage()
+            .instance()
+            .set(&DataKey::AllowedTokens, &allowed_tokens);
+
+        let topics = ("AllowedTokenRemovedEvent", token);
+        publish_event(&env, topics, ());
+    }
+
+    pub fn get_allowed_tokens(env: Env) -> Vec<Address> {
+        read_allowed_tokens(&env)
+    }
+
+    /// While no chain is registered locks may target any `dest_chain`.
+    pub fn register_chain(env: Env, chain_id: Bytes) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let mut supported_chains = read_supported_chains(&env);
+        if supported_chains.contains_key(chain_id.clone()) {
+            panic_with_error!(&env, ExtraError::ChainAlreadyRegistered);
+        }
+        supported_chains.set(chain_id.clone(), ReleaseScheme::SingleAdmin);
+        env.storage()
+            .instance()
+            .set(&ExtraKey::SupportedChains, &supported_chains);
+
+        let topics = ("ChainRegisteredEvent", chain_id);
+        publish_event(&env, topics, ());
+    }
+
+    pub fn unregister_chain(env: Env, chain_id: Bytes) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let mut supported_chains = read_supported_chains(&env);
+        if supported_chains.remove(chain_id.clone()).is_none() {
+            panic_with_error!(&env, LockError::ChainNotFound);
+        }
+        env.storage()
+            .instance()
+            .set(&ExtraKey::SupportedChains, &supported_chains);
+
+        let topics = ("ChainUnregisteredEvent", chain_id);
+        publish_event(&env, topics, ());
+    }
+
+    /// Sets how releases to a registered chain are authorized. Multisig
+    /// thresholds must be between 1 and the number of signers.
+    pub fn set_chain_release_scheme(env: Env, chain_id: Bytes, scheme: ReleaseScheme) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let mut supported_chains = read_supported_chains(&env);
+        if !supported_chains.contains_key(chain_id.clone()) {
+            panic_with_error!(&env, LockError::ChainNotFound);
+        }
+        if let ReleaseScheme::Multisig(threshold, signers) = &scheme {
+            if *threshold == 0 || *threshold > signers.len() {
+                panic_with_error!(&env, ExtraError::InvalidReleaseScheme);
+            }
+        }
+        supported_chains.set(chain_id.clone(), scheme.clone());
+        env.storage()
+            .instance()
+            .set(&ExtraKey::SupportedChains, &supported_chains);
+
+        let topics = ("ChainReleaseSchemeSetEvent", chain_id);
+        publish_event(&env, topics, scheme);
+    }
+
+    /// The release scheme for `chain_id`; `SingleAdmin` for unregistered chains.
+    pub fn get_chain_release_scheme(env: Env, chain_id: Bytes) -> ReleaseScheme {
+        read_release_scheme(&env, &chain_id)
+    }
+
+    /// Whether `lock` accepts `chain_id`: true for every chain until one is
+    /// registered.
+    pub fn is_chain_supported(env: Env, chain_id: Bytes) -> bool {
+        let supported_chains = read_supported_chains(&env);
+        supported_chains.is_empty() || supported_chains.contains_key(chain_id)
+    }
+
+    /// Whether `lock` accepts `token`: true for every token until the
+    /// allow-list has an entry.
+    pub fn is_token_supported(env: Env, token: Address) -> bool {
+        let allowed_tokens = read_allowed_tokens(&env);
+        allowed_tokens.is_empty() || allowed_tokens.contains(&token)
+    }
+
+    /// Smallest post-fee amount a lock of `token` may carry, 1 unless configured.
+    pub fn get_min_swaped(env: Env, token: Address) -> i128 {
+        read_token_config(&env, &token).min_swaped
+    }
+
+    /// Sequence number of the most recently emitted event, 0 if none yet.
+    pub fn get_global_seq(env: Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&DataKey::GlobalSeq)
+            .unwrap_or(0)
+    }
+
+    pub fn get_token_config(env: Env, token: Address) -> TokenConfig {
+        let mut token_config = read_token_config(&env, &token);
+        token_config.decimals = token::Client::new(&env, &token).decimals();
+        token_config
+    }
+
+    pub fn set_dup_window(env: Env, dup_window_ledgers: u32) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let mut config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.dup_window_ledgers = dup_window_ledgers;
+        env.storage().instance().set(&DataKey::Config, &config);
+
+        let topics = ("DupWindowUpdatedEvent", ());
+        publish_event(&env, topics, dup_window_ledgers);
+    }
+
+    /// Changes the global fee to a whole percentage, validated the same way as
+    /// in `initialize`. `FeeUpdatedEvent` carries the old and new rates in
+    /// scaled units, like `set_fee_scaled`.
+    pub fn update_fee(env: Env, new_fee_percentage: i128) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        if !(0..=100).contains(&new_fee_percentage) {
+            panic_with_error!(&env, LockError::InvalidFee);
+        }
+
+        write_fee_scaled(&env, new_fee_percentage * (FEE_SCALE / 100));
+    }
+
+    /// Changes the global fee in basis points, for rates like 0.25% that
+    /// `update_fee` cannot express. Stored in scaled units like every fee.
+    pub fn set_fee_bps(env: Env, fee_bps: i128) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        if !(0..=10_000).contains(&fee_bps) {
+            panic_with_error!(&env, LockError::InvalidFee);
+        }
+
+        write_fee_scaled(&env, fee_bps * (FEE_SCALE / 10_000));
+    }
+
+    /// Changes the accepted lock range; a `max_lock` of 0 removes the cap.
+    pub fn update_lock_limits(env: Env, min_lock: i128, max_lock: i128) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        check_lock_bounds(&env, min_lock, max_lock);
+
+        let mut config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.min_lock = min_lock;
+        config.max_lock = max_lock;
+        env.storage().instance().set(&DataKey::Config, &config);
+
+        let topics = ("LockLimitsUpdatedEvent", ());
+        publish_event(&env, topics, (min_lock, max_lock));
+    }
+
+    pub fn set_fee_scaled(env: Env, fee_scaled: i128) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        if !(0..=FEE_SCALE).contains(&fee_scaled) {
+            panic_with_error!(&env, LockError::InvalidFee);
+        }
+
+        write_fee_scaled(&env, fee_scaled);
+    }
+
+    /// Splits the cost of locking `in_amount` of `from_token` into its layers,
+    /// using the same rates `lock` would apply. Fees do not vary by destination
+    /// chain yet.
+    pub fn cost_breakdown(env: Env, from_token: Address, in_amount: i128, _dest_chain: Bytes) -> CostBreakdown {
+        if in_amount < 1 {
+            panic_with_error!(&env, LockError::InvalidAmount);
+        }
+
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        let token_config = read_token_config(&env, &from_token);
+        let base_fee = compute_fee(&env, in_amount, config.fee_scaled);
+        let fee_scaled = effective_fee_scaled(&env, &config, &token_config, &from_token);
+        let fee = compute_fee(&env, in_amount, fee_scaled);
+        let (remainder, _) = carry_remainder(&env, &from_token, in_amount, fee_scaled);
+
+        CostBreakdown {
+            in_amount,
+            base_fee,
+            surcharge: (fee - base_fee).max(0),
+            discount: (base_fee - fee).max(0),
+            fee,
+            remainder,
+            net_out: in_amount - fee - remainder,
+        }
+    }
+
+    /// Predicts a lock and its full release without changing state. Takes the
+    /// user as well, since it is part of the stored `LockData`.
+    pub fn simulate_round_trip(
+        env: Env,
+        user_address: Address,
+        from_token: Address,
+        in_amount: i128,
+        dest_token: String,
+        dest_chain: Bytes,
+        recipient_address: String,
+    ) -> RoundTripResult {
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        let fee_scaled_applied =
+            effective_fee_scaled(&env, &config, &read_token_config(&env, &from_token), &from_token);
+        let breakdown = Self::cost_breakdown(env.clone(), from_token.clone(), in_amount, dest_chain.clone());
+
+        RoundTripResult {
+            lock_id: env
+                .storage()
+                .instance()
+                .get(&DataKey::LockCounter)
+                .unwrap_or(0),
+            lock_data: LockData {
+                user_address,
+                dest_token,
+                from_token: from_token.clone(),
+                in_amount,
+                swaped_amount: breakdown.net_out,
+                recipient_address,
+                dest_chain,
+                created_ledger: env.ledger().sequence(),
+                refund_fallback: None,
+                fee_scaled_applied,
+                memo: None,
+                priority: 0,
+                remainder_charged: breakdown.remainder,
+                fee_payee: planned_fee_payee(&env, &config, breakdown.fee),
+            },
+            requires_approval: config.high_value_threshold > 0
+                && in_amount >= config.high_value_threshold,
+            release_amount: breakdown.net_out,
+        }
+    }
+
+    pub fn get_fee_scaled(env: Env) -> i128 {
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.fee_scaled
+    }
+
+    pub fn set_dynamic_fee(env: Env, dynamic_fee: DynamicFee) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        if dynamic_fee.min_fee_bps < 0
+            || dynamic_fee.min_fee_bps > dynamic_fee.max_fee_bps
+            || dynamic_fee.max_fee_bps > 10_000
+            || dynamic_fee.target_daily_volume < 1
+        {
+            panic_with_error!(&env, LockError::InvalidFee);
+        }
+
+        env.storage()
+            .instance()
+            .set(&DataKey::DynamicFee, &dynamic_fee);
+
+        let topics = ("DynamicFeeUpdatedEvent", ());
+        publish_event(&env, topics, dynamic_fee);
+    }
+
+    pub fn clear_dynamic_fee(env: Env) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        env.storage().instance().remove(&DataKey::DynamicFee);
+
+        let topics = ("DynamicFeeClearedEvent", ());
+        publish_event(&env, topics, ());
+    }
+
+    pub fn get_dynamic_fee(env: Env) -> Option<DynamicFee> {
+        env.storage().instance().get(&DataKey::DynamicFee)
+    }
+
+    pub fn set_memo_schema(env: Env, memo_schema: MemoSchema) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        if memo_schema.max_len > 0
+            && memo_schema.max_len < memo_schema.min_len.max(memo_schema.prefix.len())
+        {
+            panic_with_error!(&env, LockError::InvalidConfig);
+        }
+
+        env.storage()
+            .instance()
+            .set(&DataKey::MemoSchema, &memo_schema);
+
+        let topics = ("MemoSchemaUpdatedEvent", ());
+        publish_event(&env, topics, memo_schema);
+    }
+
+    pub fn clear_memo_schema(env: Env) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        env.storage().instance().remove(&DataKey::MemoSchema);
+
+        let topics = ("MemoSchemaClearedEvent", ());
+        publish_event(&env, topics, ());
+    }
+
+    pub fn get_memo_schema(env: Env) -> Option<MemoSchema> {
+        env.storage().instance().get(&DataKey::MemoSchema)
+    }
+
+    pub fn get_daily_volume(env: Env, token: Address) -> i128 {
+        read_daily_volume(&env, &token)
+    }
+
+    pub fn set_max_pending_per_user(env: Env, max_pending_per_user: u32) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let mut config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.max_pending_per_user = max_pending_per_user;
+        env.storage().instance().set(&DataKey::Config, &config);
+
+        let topics = ("MaxPendingPerUserUpdatedEvent", ());
+        publish_event(&env, topics, max_pending_per_user);
+    }
+
+    pub fn get_max_pending_per_user(env: Env) -> u32 {
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.max_pending_per_user
+    }
+
+    /// Number of the user's locks that are still pending or processing.
+    pub fn get_pending_count(env: Env, user: Address) -> u32 {
+        read_pending_count(&env, &user)
+    }
+
+    pub fn get_dup_window(env: Env) -> u32 {
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.dup_window_ledgers
+    }
+
+    pub fn set_chain_name(env: Env, name: String, dest_chain: Bytes) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        // Drop the reverse entry of a chain the name previously pointed at
+        let previous: Option<Bytes> = env
+            .storage()
+            .instance()
+            .get(&DataKey::ChainByName(name.clone()));
+        if let Some(previous) = previous {
+            env.storage().instance().remove(&DataKey::ChainName(previous));
+        }
+        // And the chain's own previous name when it is being renamed
+        let previous_name: Option<String> = env
+            .storage()
+            .instance()
+            .get(&DataKey::ChainName(dest_chain.clone()));
+        if let Some(previous_name) = previous_name {
+            env.storage()
+                .instance()
+                .remove(&DataKey::ChainByName(previous_name));
+        }
+
+        env.storage()
+            .instance()
+            .set(&DataKey::ChainByName(name.clone()), &dest_chain);
+        env.storage()
+            .instance()
+            .set(&DataKey::ChainName(dest_chain.clone()), &name);
+
+        let topics = ("ChainNameSetEvent", name);
+        publish_event(&env, topics, dest_chain);
+    }
+
+    pub fn remove_chain_name(env: Env, name: String) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let dest_chain = resolve_chain_name(&env, &name);
+        env.storage()
+            .instance()
+            .remove(&DataKey::ChainByName(name.clone()));
+        env.storage()
+            .instance()
+            .remove(&DataKey::ChainName(dest_chain.clone()));
+
+        let topics = ("ChainNameRemovedEvent", name);
+        publish_event(&env, topics, dest_chain);
+    }
+
+    pub fn set_chain_admin(env: Env, caller: Address, dest_chain: Bytes, admin: Address) {
+        require_role_admin(&env, &caller);
+
+        // A chain admin releases funds, so the owner may not be one either
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        if config.require_role_separation && admin == owner {
+            panic_with_error!(&env, LockError::RoleSeparationViolated);
+        }
+
+        env.storage()
+            .instance()
+            .set(&DataKey::ChainAdmin(dest_chain.clone()), &admin);
+        let mut chains = read_chain_admin_chains(&env);
+        if !chains.contains(&dest_chain) {
+            chains.push_back(dest_chain.clone());
+            env.storage()
+                .instance()
+                .set(&ExtraKey::ChainAdminChains, &chains);
+        }
+
+        let topics = ("ChainAdminSetEvent", dest_chain);
+        publish_event(&env, topics, admin);
+    }
+
+    pub fn remove_chain_admin(env: Env, caller: Address, dest_chain: Bytes) {
+        require_role_admin(&env, &caller);
+
+        env.storage()
+            .instance()
+            .remove(&DataKey::ChainAdmin(dest_chain.clone()));
+        let mut chains = read_chain_admin_chains(&env);
+        if let Some(index) = chains.first_index_of(&dest_chain) {
+            chains.remove(index);
+            env.storage()
+                .instance()
+                .set(&ExtraKey::ChainAdminChains, &chains);
+        }
+
+        let topics = ("ChainAdminRemovedEvent", dest_chain);
+        publish_event(&env, topics, ());
+    }
+
+    /// The admin authorizing releases for `dest_chain`, falling back to the
+    /// global admin when the chain has none of its own.
+    pub fn admin_for_chain(env: Env, dest_chain: Bytes) -> Address {
+        read_chain_admin(&env, &dest_chain)
+    }
+
+    pub fn add_dest_token(env: Env, dest_token: String) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        env.storage()
+            .instance()
+            .set(&DataKey::KnownDestToken(dest_token.clone()), &());
+
+        let topics = ("DestTokenAddedEvent", dest_token);
+        publish_event(&env, topics, ());
+    }
+
+    pub fn remove_dest_token(env: Env, dest_token: String) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        env.storage()
+            .instance()
+            .remove(&DataKey::KnownDestToken(dest_token.clone()));
+
+        let topics = ("DestTokenRemovedEvent", dest_token);
+        publish_event(&env, topics, ());
+    }
+
+    pub fn is_known_dest_token(env: Env, dest_token: String) -> bool {
+        env.storage()
+            .instance()
+            .has(&DataKey::KnownDestToken(dest_token))
+    }
+
+    /// When on, `lock` only accepts `dest_token` identifiers added with
+    /// `add_dest_token`.
+    pub fn set_validate_dest_token(env: Env, validate_dest_token: bool) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let mut config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.validate_dest_token = validate_dest_token;
+        env.storage().instance().set(&DataKey::Config, &config);
+
+        let topics = ("ValidateDestTokenUpdatedEvent", ());
+        publish_event(&env, topics, validate_dest_token);
+    }
+
+    pub fn get_validate_dest_token(env: Env) -> bool {
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.validate_dest_token
+    }
+
+    /// Links a `dest_token` identifier to the token released for it here.
+    pub fn set_dest_token_address(env: Env, dest_token: String, token: Address) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        env.storage()
+            .instance()
+            .set(&ExtraKey::DestTokenAddress(dest_token.clone()), &token);
+
+        let topics = ("DestTokenAddressSetEvent", dest_token);
+        publish_event(&env, topics, token);
+    }
+
+    pub fn remove_dest_token_address(env: Env, dest_token: String) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        env.storage()
+            .instance()
+            .remove(&ExtraKey::DestTokenAddress(dest_token.clone()));
+
+        let topics = ("DestTokenAddressRemovedEvent", dest_token);
+        publish_event(&env, topics, ());
+    }
+
+    pub fn get_dest_token_address(env: Env, dest_token: String) -> Option<Address> {
+        env.storage()
+            .instance()
+            .get(&ExtraKey::DestTokenAddress(dest_token))
+    }
+
+    /// When on, a release against a lock must pay out the token mapped to the
+    /// lock's `dest_token` with `set_dest_token_address`; unmapped ones fail.
+    pub fn set_enforce_dest_token_match(env: Env, enforce_dest_token_match: bool) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let mut config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.enforce_dest_token_match = enforce_dest_token_match;
+        env.storage().instance().set(&DataKey::Config, &config);
+
+        let topics = ("EnforceDestTokenMatchUpdatedEvent", ());
+        publish_event(&env, topics, enforce_dest_token_match);
+    }
+
+    pub fn get_enforce_dest_token_match(env: Env) -> bool {
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.enforce_dest_token_match
+    }
+
+    /// When on, `lock` keeps each lock's `swaped_amount` in custody instead of
+    /// forwarding it to the admin. Held locks are paid out by the custody
+    /// release paths and refunded out of custody without the admin's balance.
+    pub fn set_hold_principal(env: Env, hold_principal: bool) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let mut config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.hold_principal = hold_principal;
+        env.storage().instance().set(&DataKey::Config, &config);
+
+        let topics = ("HoldPrincipalUpdatedEvent", ());
+        publish_event(&env, topics, hold_principal);
+    }
+
+    pub fn get_hold_principal(env: Env) -> bool {
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.hold_principal
+    }
+
+    pub fn resolve_chain(env: Env, name: String) -> Bytes {
+        resolve_chain_name(&env, &name)
+    }
+
+    pub fn chain_name(env: Env, dest_chain: Bytes) -> String {
+        match env.storage().instance().get(&DataKey::ChainName(dest_chain)) {
+            Some(name) => name,
+            None => panic_with_error!(&env, LockError::ChainNotFound),
+        }
+    }
+
+    pub fn lock_to_chain(
+        env: Env,
+        user_address: Address,
+        from_token: Address,
+        dest_token: String,
+        in_amount: i128,
+        chain_name: String,
+        recipient_address: String,
+        force: bool,
+        refund_fallback: Option<Address>,
+        memo: Option<Bytes>,
+        priority: u32,
+    ) -> u64 {
+        let dest_chain = resolve_chain_name(&env, &chain_name);
+        Self::lock(
+            env,
+            user_address,
+            from_token,
+            dest_token,
+            in_amount,
+            dest_chain,
+            recipient_address,
+            force,
+            refund_fallback,
+            memo,
+            priority,
+        )
+    }
+
+    pub fn lock(
+        env: Env,
+        user_address: Address,
+        from_token: Address,
+        dest_token: String,
+        in_amount: i128,
+        dest_chain: Bytes,
+        recipient_address: String,
+        force: bool,
+        refund_fallback: Option<Address>,
+        memo: Option<Bytes>,
+        priority: u32,
+    ) -> u64 {
+        // Check if contract is paused before proceeding
+        check_if_paused(&env);
+
+        // Set re-entrancy guard
+        check_and_set_reentrancy_guard(&env);
+        
+        // Authorization and input validation
+        user_address.require_auth();
+        if in_amount < 1 {
+            panic_with_error!(&env, LockError::InvalidAmount);
+        }
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        if in_amount < config.min_lock {
+            panic_with_error!(&env, ExtraError::AmountBelowMinimum);
+        }
+        if config.max_lock > 0 && in_amount > config.max_lock {
+            panic_with_error!(&env, ExtraError::AmountAboveMaximum);
+        }
+
+        // A refund fallback must be a different, external address
+        if let Some(fallback) = &refund_fallback {
+            if *fallback == user_address || *fallback == env.current_contract_address() {
+                panic_with_error!(&env, LockError::InvalidRefundFallback);
+            }
+        }
+
+        // Enforce the registered memo schema, if any
+        check_memo(&env, &memo);
+        if priority > MAX_PRIORITY {
+            panic_with_error!(&env, LockError::InvalidPriority);
+        }
+
+        // Check if an admin exists
+        if !env.storage().instance().has(&DataKey::Admin) {
+            panic_with_error!(&env, LockError::AdminNotSet);
+        }
+
+        // Once an allow-list exists, only listed tokens may be locked
+        let allowed_tokens = read_allowed_tokens(&env);
+        if !allowed_tokens.is_empty() && !allowed_tokens.contains(&from_token) {
+            panic_with_error!(&env, LockError::UnsupportedToken);
+        }
+
+        // Likewise, once any chain is registered only those may be targeted
+        let supported_chains = read_supported_chains(&env);
+        if !supported_chains.is_empty() && !supported_chains.contains_key(dest_chain.clone()) {
+            panic_with_error!(&env, ExtraError::UnsupportedChain);
+        }
+
+        // Reject tokens paused individually by the owner
+        let token_config = read_token_config(&env, &from_token);
+        if token_config.paused {
+            panic_with_error!(&env, LockError::TokenPaused);
+        }
+
+        // Verify user's balance before proceeding
+        let user_balance = token::Client::new(&env, &from_token).balance(&user_address);
+        if user_balance < in_amount {
+            panic_with_error!(&env, LockError::InsufficientBalance);
+        }
+
+        // Catch destination tokens the relayer would not be able to map
+        if config.validate_dest_token
+            && !env
+                .storage()
+                .instance()
+                .has(&DataKey::KnownDestToken(dest_token.clone()))
+        {
+            panic_with_error!(&env, LockError::UnknownDestToken);
+        }
+
+        // No new intake once the contract has reached its sunset ledger
+        if config.sunset_ledger > 0 && env.ledger().sequence() >= config.sunset_ledger {
+            panic_with_error!(&env, LockError::ContractSunset);
+        }
+
+        // Cap how many unsettled locks a single user can have outstanding
+        if config.max_pending_per_user > 0
+            && read_pending_count(&env, &user_address) >= config.max_pending_per_user
+        {
+            panic_with_error!(&env, LockError::TooManyPendingLocks);
+        }
+
+        // Reject an identical lock from the same user inside the duplicate window
+        let lock_hash: BytesN<32> = env
+            .crypto()
+            .sha256(
+                &(
+                    from_token.clone(),
+                    in_amount,
+                    dest_chain.clone(),
+                    recipient_address.clone(),
+                )
+                    .to_xdr(&env),
+            )
+            .into();
+        let current_ledger = env.ledger().sequence();
+        if !force && config.dup_window_ledgers > 0 {
+            let last_lock: Option<LastLock> = env
+                .storage()
+                .instance()
+                .get(&DataKey::LastLock(user_address.clone()));
+            if let Some(last_lock) = last_lock {
+                if last_lock.hash == lock_hash
+                    && current_ledger - last_lock.ledger < config.dup_window_ledgers
+                {
+                    panic_with_error!(&env, LockError::DuplicateLock);
+                }
+            }
+        }
+        env.storage().instance().set(
+            &DataKey::LastLock(user_address.clone()),
+            &LastLock {
+                hash: lock_hash,
+                ledger: current_ledger,
+            },
+        );
+
+        // Fee and swap calculations
+        let fee_scaled_applied = effective_fee_scaled(&env, &config, &token_config, &from_token);
+        let fee = compute_fee(&env, in_amount, fee_scaled_applied);
+        let (remainder_charged, protocol_remainder) =
+            carry_remainder(&env, &from_token, in_amount, fee_scaled_applied);
+        env.storage().instance().set(
+            &DataKey::ProtocolRemainder(from_token.clone()),
+            &protocol_remainder,
+        );
+        let swaped_amount = match in_amount
+            .checked_sub(fee)
+            .and_then(|net| net.checked_sub(remainder_charged))
+        {
+            Some(swaped_amount) => swaped_amount,
+            None => panic_with_error!(&env, LockError::AmountOverflow),
+        };
+        add_daily_volume(&env, &from_token, in_amount);
+        record_fee_stats(&env, in_amount, fee);
+        record_user_lock(&env, &user_address, in_amount, fee + remainder_charged);
+
+        record_known_token(&env, &from_token);
+
+        // Ensure the amount after fee is meaningful for this token
+        if swaped_amount < token_config.min_swaped {
+            panic_with_error!(&env, LockError::SwapAmountTooSmall);
+        }
+
+        // Update state before external interactions
+        let lock_id: u64 = env
+            .storage()
+            .instance()
+            .get(&DataKey::LockCounter)
+            .unwrap_or(0);
+        env.storage()
+            .instance()
+            .set(&DataKey::LockCounter, &(lock_id + 1));
+        let mut lock_data = LockData {
+            user_address: user_address.clone(),
+            dest_token: dest_token.clone(),
+            from_token: from_token.clone(),
+            in_amount,
+            swaped_amount,
+            recipient_address,
+            dest_chain,
+            created_ledger: current_ledger,
+            refund_fallback,
+            fee_scaled_applied,
+            memo,
+            priority,
+            remainder_charged,
+            fee_payee: None,
+        };
+        env.storage()
+            .instance()
+            .set(&DataKey::Lock(lock_id), &lock_data);
+        append_user_lock(&env, &user_address, lock_id);
+        record_activity(&env, false);
+
+        // Large locks wait for an explicit admin approval before release
+        let high_value =
+            config.high_value_threshold > 0 && in_amount >= config.high_value_threshold;
+        if high_value {
+            write_lock_status(&env, lock_id, LockStatus::PendingApproval);
+            let topics = ("HighValueLockEvent", lock_id, user_address.clone());
+            publish_event(&env, topics, in_amount);
+        } else {
+            write_lock_status(&env, lock_id, LockStatus::Pending);
+        }
+        check_cap_warning(
+            &env,
+            "pending_per_user",
+            read_pending_count(&env, &user_address).into(),
+            config.max_pending_per_user.into(),
+        );
+
+        // Perform token transfers (interaction with external contract). The
+        // swapped portion goes straight to the admin, or stays in custody when
+        // the owner holds principal, so a zero-fee lock moves funds exactly once.
+        let token_client = token::Client::new(&env, &from_token);
+        let admin_data: AdminData = match env.storage().instance().get(&DataKey::Admin) {
+            Some(admin_data) => admin_data,
+            None => panic_with_error!(&env, LockError::AdminNotSet),
+        };
+        if config.hold_principal {
+            token_client.transfer(&user_address, &read_custodian(&env, &from_token), &swaped_amount);
+            let total = read_held_principal_total(&env, &from_token) + swaped_amount;
+            write_held_principal(&env, lock_id, &from_token, swaped_amount, total);
+        } else {
+            token_client.transfer(&user_address, &admin_data.admin_address, &swaped_amount);
+        }
+        // Fees worth a transfer of their own go straight to the payee; dust is
+        // held for a later flush_to_admin
+        let planned_payee = planned_fee_payee(&env, &config, fee);
+        let forwarded = planned_payee
+            .clone()
+            .and_then(|fee_payee| forward_fee(&env, &token_client, &user_address, fee_payee, fee));
+        match forwarded {
+            Some(fee_payee) => {
+                // Refunds reclaim the fee from whoever received it here
+                lock_data.fee_payee = Some(fee_payee.clone());
+                env.storage()
+                    .instance()
+                    .set(&DataKey::Lock(lock_id), &lock_data);
+
+                let topics = ("FeeForwardedEvent", lock_id, fee_payee);
+                publish_event(&env, topics, fee);
+            }
+            // Fees no payee would take are held like dust
+            None if fee > 0 => {
+                token_client.transfer(&user_address, &read_custodian(&env, &from_token), &fee);
+                write_accrued_fees(&env, &from_token, read_accrued_fees(&env, &from_token) + fee);
+
+                // Track a failed forward so operators can retry it
+                if planned_payee.is_some() {
+                    let forward_state = ForwardState {
+                        fee,
+                        attempts: 1,
+                        last_attempt_ledger: current_ledger,
+                        forwarded_to: None,
+                    };
+                    env.storage()
+                        .instance()
+                        .set(&ExtraKey::ForwardState(lock_id), &forward_state);
+
+                    let topics = ("FeeForwardFailedEvent", lock_id);
+                    publish_event(&env, topics, forward_state);
+                }
+            }
+            None => {}
+        }
+        if remainder_charged > 0 {
+            token_client.transfer(
+                &user_address,
+                &read_custodian(&env, &from_token),
+                &remainder_charged,
+            );
+        }
+
+        // Commit to the transfer under the sequence number of its lock event
+        let global_seq: u64 = env
+            .storage()
+            .instance()
+            .get(&DataKey::GlobalSeq)
+            .unwrap_or(0)
+            + 1;
+        let commitment: BytesN<32> = env
+            .crypto()
+            .sha256(
+                &(
+                    env.current_contract_address(),
+                    global_seq,
+                    user_address.clone(),
+                    dest_token.clone(),
+                    swaped_amount,
+                    lock_data.recipient_address.clone(),
+                    lock_data.dest_chain.clone(),
+                )
+                    .to_xdr(&env),
+            )
+            .into();
+        env.storage()
+            .instance()
+            .set(&ExtraKey::BridgeCommitment(lock_id), &commitment);
+
+        // Publish lock event
+        let topics = (
+            "LockEvent",
+            lock_id,
+            user_address,
+            dest_token,
+            in_amount,
+            swaped_amount,
+        );
+        publish_event(&env, topics, (lock_data, commitment));
+
+        // Clear re-entrancy guard
+        clear_reentrancy_guard(&env);
+
+        lock_id
+    }
+
+    /// The commitment published with a lock for the destination chain to
+    /// verify: sha256 over the XDR of `(contract, global_seq, user, dest_token,
+    /// swaped_amount, recipient_address, dest_chain)`, where `global_seq` is
+    /// that of the lock's `LockEvent`. Imported locks have none.
+    pub fn bridge_commitment(env: Env, lock_id: u64) -> BytesN<32> {
+        match env
+            .storage()
+            .instance()
+            .get(&ExtraKey::BridgeCommitment(lock_id))
+        {
+            Some(commitment) => commitment,
+            None => panic_with_error!(&env, LockError::LockNotFound),
+        }
+    }
+
+    /// Returns an unsettled lock's full `in_amount` to its user, or to the
+    /// lock's `refund_fallback` if the user can no longer receive the token.
+    /// The admin holds the swapped portion, so it co-signs the refund.
+    pub fn refund(env: Env, lock_id: u64) {
+        // Set re-entrancy guard
+        check_and_set_reentrancy_guard(&env);
+
+        let admin_data: AdminData = match env.storage().instance().get(&DataKey::Admin) {
+            Some(admin_data) => admin_data,
+            None => panic_with_error!(&env, LockError::AdminNotSet),
+        };
+        admin_data.admin_address.require_auth();
+
+        if in_refund_grace(&env, lock_id) {
+            panic_with_error!(&env, ExtraError::RefundGraceActive);
+        }
+        refund_lock(&env, lock_id, &admin_data.admin_address, 0);
+
+        // Clear re-entrancy guard
+        clear_reentrancy_guard(&env);
+    }
+
+    /// Refunds every unsettled lock in `lock_ids` free of charge, skipping
+    /// unknown, settled, partly released and still-in-grace ones, and returns
+    /// the ids refunded. The
+    /// admin cosigns since the swapped portions come from its balance.
+    pub fn refund_batch(env: Env, lock_ids: Vec<u64>) -> Vec<u64> {
+        // Set re-entrancy guard
+        check_and_set_reentrancy_guard(&env);
+
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+        let admin_data: AdminData = match env.storage().instance().get(&DataKey::Admin) {
+            Some(admin_data) => admin_data,
+            None => panic_with_error!(&env, LockError::AdminNotSet),
+        };
+        admin_data.admin_address.require_auth();
+
+        check_batch_size(&env, lock_ids.len());
+
+        let mut refunded = Vec::new(&env);
+        for lock_id in lock_ids.iter() {
+            if env.storage().instance().has(&DataKey::Lock(lock_id))
+                && is_unsettled(read_lock_status(&env, lock_id))
+                && read_released_amount(&env, lock_id) == 0
+                && !in_refund_grace(&env, lock_id)
+            {
+                refund_lock(&env, lock_id, &admin_data.admin_address, 0);
+                refunded.push_back(lock_id);
+            }
+        }
+
+        let topics = ("RefundBatchEvent", lock_ids.len());
+        publish_event(&env, topics, refunded.clone());
+
+        // Clear re-entrancy guard
+        clear_reentrancy_guard(&env);
+
+        refunded
+    }
+
+    /// Lets a lock's user hold off admin refunds for `extra_ledgers` more
+    /// ledgers, e.g. while a slow relay is still expected to deliver. The
+    /// refund-eligible ledger only ever moves later, and the user's own
+    /// `cancel_lock` is unaffected. Returns the new refund-eligible ledger.
+    pub fn extend_refund_grace(env: Env, lock_id: u64, extra_ledgers: u32) -> u32 {
+        let lock_data = read_lock(&env, lock_id);
+        lock_data.user_address.require_auth();
+
+        if !is_unsettled(read_lock_status(&env, lock_id)) {
+            panic_with_error!(&env, LockError::InvalidLockStatus);
+        }
+        if extra_ledgers == 0 {
+            panic_with_error!(&env, LockError::InvalidAmount);
+        }
+
+        // Extend from now if the previous grace has already run out
+        let eligible_ledger = read_refund_grace(&env, lock_id, &lock_data)
+            .max(env.ledger().sequence())
+            .saturating_add(extra_ledgers);
+        env.storage()
+            .instance()
+            .set(&ExtraKey::RefundGrace(lock_id), &eligible_ledger);
+
+        let topics = ("RefundGraceExtendedEvent", lock_id);
+        publish_event(&env, topics, eligible_ledger);
+
+        eligible_ledger
+    }
+
+    /// Refunds a lock at its owner's request, e.g. when the destination-chain
+    /// release never happens. Unlike an admin `refund`, this keeps
+    /// `refund_fee_bps` of the locked amount as a fee. The admin still cosigns
+    /// since the swapped portion is returned from its balance, and released
+    /// locks cannot be cancelled.
+    pub fn cancel_lock(env: Env, lock_id: u64) {
+        // Set re-entrancy guard
+        check_and_set_reentrancy_guard(&env);
+
+        let lock_data = read_lock(&env, lock_id);
+        lock_data.user_address.require_auth();
+        let admin_data: AdminData = match env.storage().instance().get(&DataKey::Admin) {
+            Some(admin_data) => admin_data,
+            None => panic_with_error!(&env, LockError::AdminNotSet),
+        };
+        admin_data.admin_address.require_auth();
+
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        refund_lock(&env, lock_id, &admin_data.admin_address, config.refund_fee_bps);
+
+        // Clear re-entrancy guard
+        clear_reentrancy_guard(&env);
+    }
+
+    pub fn override_recipient(env: Env, lock_id: u64, new_recipient: String) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let mut lock_data = read_lock(&env, lock_id);
+        if !is_unsettled(read_lock_status(&env, lock_id)) {
+            panic_with_error!(&env, LockError::InvalidLockStatus);
+        }
+
+        // Keep the recipient the user originally submitted across repeated overrides
+        if !env
+            .storage()
+            .instance()
+            .has(&DataKey::RecipientOverride(lock_id))
+        {
+            env.storage().instance().set(
+                &DataKey::RecipientOverride(lock_id),
+                &RecipientOverride {
+                    original_recipient: lock_data.recipient_address.clone(),
+                    ledger: env.ledger().sequence(),
+                },
+            );
+        }
+
+        let old_recipient = lock_data.recipient_address.clone();
+        lock_data.recipient_address = new_recipient.clone();
+        env.storage()
+            .instance()
+            .set(&DataKey::Lock(lock_id), &lock_data);
+
+        let topics = ("RecipientOverriddenEvent", lock_id);
+        publish_event(&env, topics, (old_recipient, new_recipient));
+    }
+
+    pub fn set_min_confirmations(env: Env, min_confirmations: u32) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let mut config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.min_confirmations = min_confirmations;
+        env.storage().instance().set(&DataKey::Config, &config);
+
+        let topics = ("MinConfirmationsUpdatedEvent", ());
+        publish_event(&env, topics, min_confirmations);
+    }
+
+    pub fn get_min_confirmations(env: Env) -> u32 {
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.min_confirmations
+    }
+
+    /// Releases against a lock are refused until it is this many ledgers old.
+    /// Zero disables the wait.
+    pub fn set_min_lock_age_ledgers(env: Env, min_lock_age_ledgers: u32) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let mut config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.min_lock_age_ledgers = min_lock_age_ledgers;
+        env.storage().instance().set(&DataKey::Config, &config);
+
+        let topics = ("MinLockAgeUpdatedEvent", ());
+        publish_event(&env, topics, min_lock_age_ledgers);
+    }
+
+    pub fn get_min_lock_age_ledgers(env: Env) -> u32 {
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.min_lock_age_ledgers
+    }
+
+    /// Emergency override letting a lock be released before it reaches
+    /// `min_lock_age_ledgers`.
+    pub fn waive_min_lock_age(env: Env, lock_id: u64) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        read_lock(&env, lock_id);
+        env.storage()
+            .instance()
+            .set(&ExtraKey::LockAgeWaived(lock_id), &());
+
+        let topics = ("LockAgeWaivedEvent", lock_id);
+        publish_event(&env, topics, owner);
+    }
+
+    pub fn set_high_value_threshold(env: Env, high_value_threshold: i128) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        if high_value_threshold < 0 {
+            panic_with_error!(&env, LockError::InvalidConfig);
+        }
+
+        let mut config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.high_value_threshold = high_value_threshold;
+        env.storage().instance().set(&DataKey::Config, &config);
+
+        let topics = ("HighValueThresholdUpdatedEvent", ());
+        publish_event(&env, topics, high_value_threshold);
+    }
+
+    /// Fees at or above this amount are forwarded on every lock; smaller ones
+    /// accrue until flushed. Zero accrues every fee.
+    pub fn set_forward_dust_threshold(env: Env, forward_dust_threshold: i128) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        if forward_dust_threshold < 0 {
+            panic_with_error!(&env, LockError::InvalidConfig);
+        }
+
+        let mut config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.forward_dust_threshold = forward_dust_threshold;
+        env.storage().instance().set(&DataKey::Config, &config);
+
+        let topics = ("ForwardDustThresholdUpdatedEvent", ());
+        publish_event(&env, topics, forward_dust_threshold);
+    }
+
+    /// Locking stops once the ledger sequence reaches `sunset_ledger`; releases
+    /// and refunds are unaffected. Zero removes the sunset.
+    pub fn set_sunset_ledger(env: Env, sunset_ledger: u32) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let mut config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.sunset_ledger = sunset_ledger;
+        env.storage().instance().set(&DataKey::Config, &config);
+
+        let topics = ("SunsetLedgerUpdatedEvent", ());
+        publish_event(&env, topics, sunset_ledger);
+    }
+
+    pub fn get_sunset_ledger(env: Env) -> u32 {
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.sunset_ledger
+    }
+
+    pub fn get_forward_dust_threshold(env: Env) -> i128 {
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.forward_dust_threshold
+    }
+
+    pub fn get_high_value_threshold(env: Env) -> i128 {
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.high_value_threshold
+    }
+
+    pub fn approve_lock(env: Env, lock_id: u64) {
+        let admin_data: AdminData = match env.storage().instance().get(&DataKey::Admin) {
+            Some(admin_data) => admin_data,
+            None => panic_with_error!(&env, LockError::AdminNotSet),
+        };
+        admin_data.admin_address.require_auth();
+
+        read_lock(&env, lock_id);
+        if read_lock_status(&env, lock_id) != LockStatus::PendingApproval {
+            panic_with_error!(&env, LockError::InvalidLockStatus);
+        }
+
+        write_lock_status(&env, lock_id, LockStatus::Pending);
+
+        let topics = ("LockApprovedEvent", lock_id);
+        publish_event(&env, topics, admin_data.admin_address);
+    }
+
+    pub fn mark_processing(env: Env, lock_id: u64) {
+        let admin_data: AdminData = match env.storage().instance().get(&DataKey::Admin) {
+            Some(admin_data) => admin_data,
+            None => panic_with_error!(&env, LockError::AdminNotSet),
+        };
+        admin_data.admin_address.require_auth();
+
+        let lock_data = read_lock(&env, lock_id);
+        if read_lock_status(&env, lock_id) != LockStatus::Pending || !is_confirmed(&env, &lock_data)
+        {
+            panic_with_error!(&env, LockError::InvalidLockStatus);
+        }
+
+        write_lock_status(&env, lock_id, LockStatus::Processing);
+
+        let topics = ("LockProcessingEvent", lock_id);
+        publish_event(&env, topics, ());
+    }
+
+    /// Moves every eligible lock in `ids` to `Processing`, skipping unknown,
+    /// unconfirmed or non-pending ones, and returns the ids that transitioned.
+    pub fn mark_processing_batch(env: Env, ids: Vec<u64>) -> Vec<u64> {
+        let admin_data: AdminData = match env.storage().instance().get(&DataKey::Admin) {
+            Some(admin_data) => admin_data,
+            None => panic_with_error!(&env, LockError::AdminNotSet),
+        };
+        admin_data.admin_address.require_auth();
+
+        check_batch_size(&env, ids.len());
+
+        let mut transitioned = Vec::new(&env);
+        for lock_id in ids.iter() {
+            let lock_data: Option<LockData> = env.storage().instance().get(&DataKey::Lock(lock_id));
+            let eligible = match lock_data {
+                Some(lock_data) => {
+                    read_lock_status(&env, lock_id) == LockStatus::Pending
+                        && is_confirmed(&env, &lock_data)
+                }
+                None => false,
+            };
+            if eligible {
+                write_lock_status(&env, lock_id, LockStatus::Processing);
+                transitioned.push_back(lock_id);
+            }
+        }
+
+        let topics = ("LockProcessingBatchEvent", ids.len());
+        publish_event(&env, topics, transitioned.clone());
+
+        transitioned
+    }
+
+    /// Groups unsettled locks bound for the same chain and token into a new
+    /// settlement batch. A lock can only belong to one batch.
+    pub fn create_settlement_batch(env: Env, lock_ids: Vec<u64>) -> u64 {
+        let admin_data: AdminData = match env.storage().instance().get(&DataKey::Admin) {
+            Some(admin_data) => admin_data,
+            None => panic_with_error!(&env, LockError::AdminNotSet),
+        };
+        admin_data.admin_address.require_auth();
+
+        if lock_ids.is_empty() {
+            panic_with_error!(&env, LockError::EmptyBatch);
+        }
+        check_batch_size(&env, lock_ids.len());
+
+        let batch_id: u64 = env
+            .storage()
+            .instance()
+            .get(&DataKey::BatchCounter)
+            .unwrap_or(0);
+        let first = read_lock(&env, lock_ids.get_unchecked(0));
+        for lock_id in lock_ids.iter() {
+            let lock_data = read_lock(&env, lock_id);
+            if lock_data.dest_chain != first.dest_chain
+                || lock_data.dest_token != first.dest_token
+                || !is_releasable(read_lock_status(&env, lock_id))
+                || env.storage().instance().has(&DataKey::LockBatch(lock_id))
+            {
+                panic_with_error!(&env, LockError::IneligibleForBatch);
+            }
+            env.storage()
+                .instance()
+                .set(&DataKey::LockBatch(lock_id), &batch_id);
+        }
+
+        env.storage()
+            .instance()
+            .set(&DataKey::BatchCounter, &(batch_id + 1));
+        env.storage()
+            .instance()
+            .set(&DataKey::Batch(batch_id), &lock_ids);
+
+        let topics = ("SettlementBatchCreatedEvent", batch_id, first.dest_chain);
+        publish_event(&env, topics, lock_ids);
+
+        batch_id
+    }
+
+    pub fn get_batch(env: Env, batch_id: u64) -> Vec<u64> {
+        match env.storage().instance().get(&DataKey::Batch(batch_id)) {
+            Some(lock_ids) => lock_ids,
+            None => panic_with_error!(&env, LockError::BatchNotFound),
+        }
+    }
+
+    /// Pending locks from `start` onwards that have reached `min_confirmations`,
+    /// returning at most `limit` ids.
+    pub fn pending_locks(env: Env, start: u64, limit: u32) -> Vec<u64> {
+        if limit > MAX_QUERY_LEN {
+            panic_with_error!(&env, LockError::TooManyItems);
+        }
+
+        let lock_count: u64 = env
+            .storage()
+            .instance()
+            .get(&DataKey::LockCounter)
+            .unwrap_or(0);
+        let mut lock_ids = Vec::new(&env);
+        let mut lock_id = start;
+        while lock_id < lock_count && lock_ids.len() < limit {
+            if lock_exists(&env, lock_id)
+                && read_lock_status(&env, lock_id) == LockStatus::Pending
+                && is_confirmed(&env, &read_lock(&env, lock_id))
+            {
+                lock_ids.push_back(lock_id);
+            }
+            lock_id += 1;
+        }
+        lock_ids
+    }
+
+    /// Releasable locks from `start` onwards flagged with at least `min_priority`,
+    /// returning at most `limit` ids.
+    pub fn get_locks_by_priority(env: Env, min_priority: u32, start: u64, limit: u32) -> Vec<u64> {
+        if limit > MAX_QUERY_LEN {
+            panic_with_error!(&env, LockError::TooManyItems);
+        }
+
+        let lock_count: u64 = env
+            .storage()
+            .instance()
+            .get(&DataKey::LockCounter)
+            .unwrap_or(0);
+        let mut lock_ids = Vec::new(&env);
+        let mut lock_id = start;
+        while lock_id < lock_count && lock_ids.len() < limit {
+            if lock_exists(&env, lock_id)
+                && is_releasable(read_lock_status(&env, lock_id))
+                && read_lock(&env, lock_id).priority >= min_priority
+            {
+                lock_ids.push_back(lock_id);
+            }
+            lock_id += 1;
+        }
+        lock_ids
+    }
+
+    /// The instance storage key a lock's `LockData` lives under, for tooling
+    /// that reads raw contract storage.
+    pub fn lock_storage_key(_env: Env, lock_id: u64) -> DataKey {
+        DataKey::Lock(lock_id)
+    }
+
+    /// The id the next successful `lock` will be assigned. Advisory only: a
+    /// lock or import landing first takes the id and moves the counter on.
+    pub fn next_lock_id(env: Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&DataKey::LockCounter)
+            .unwrap_or(0)
+    }
+
+    pub fn get_lock(env: Env, lock_id: u64) -> LockData {
+        read_lock(&env, lock_id)
+    }
+
+    /// Returns the locks in the order requested, failing if any id is unknown.
+    pub fn get_locks_by_ids(env: Env, ids: Vec<u64>) -> Vec<LockData> {
+        if ids.len() > MAX_QUERY_LEN {
+            panic_with_error!(&env, LockError::TooManyItems);
+        }
+
+        let mut locks = Vec::new(&env);
+        for lock_id in ids.iter() {
+            locks.push_back(read_lock(&env, lock_id));
+        }
+        locks
+    }
+
+    /// Imports a lock from a previous deployment under its original id. Only
+    /// available to the owner until `finalize_migration` is called. Ids skipped
+    /// by an import are never assigned, so id scans step over them.
+    pub fn import_lock(env: Env, lock_id: u64, lock_data: LockData, status: LockStatus) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        if env.storage().instance().has(&DataKey::MigrationComplete) {
+            panic_with_error!(&env, LockError::MigrationFinalized);
+        }
+        if env.storage().instance().has(&DataKey::Lock(lock_id)) {
+            panic_with_error!(&env, LockError::LockAlreadyExists);
+        }
+        // Lock ages are measured from `created_ledger`, so it cannot lie ahead
+        if lock_data.created_ledger > env.ledger().sequence() {
+            panic_with_error!(&env, ExtraError::InvalidCreatedLedger);
+        }
+
+        env.storage()
+            .instance()
+            .set(&DataKey::Lock(lock_id), &lock_data);
+        write_lock_status(&env, lock_id, status);
+        append_user_lock(&env, &lock_data.user_address, lock_id);
+        record_known_token(&env, &lock_data.from_token);
+
+        // Keep new ids clear of every imported one
+        let lock_count: u64 = env
+            .storage()
+            .instance()
+            .get(&DataKey::LockCounter)
+            .unwrap_or(0);
+        if lock_id >= lock_count {
+            env.storage()
+                .instance()
+                .set(&DataKey::LockCounter, &(lock_id + 1));
+        }
+
+        let topics = ("LockImportedEvent", lock_id);
+        publish_event(&env, topics, status);
+    }
+
+    pub fn finalize_migration(env: Env) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        if env.storage().instance().has(&DataKey::MigrationComplete) {
+            panic_with_error!(&env, LockError::MigrationFinalized);
+        }
+
+        env.storage().instance().set(&DataKey::MigrationComplete, &());
+
+        let topics = ("MigrationFinalizedEvent", ());
+        publish_event(&env, topics, ());
+    }
+
+    pub fn is_migration_complete(env: Env) -> bool {
+        env.storage().instance().has(&DataKey::MigrationComplete)
+    }
+
+    pub fn get_recipient_override(env: Env, lock_id: u64) -> Option<RecipientOverride> {
+        env.storage()
+            .instance()
+            .get(&DataKey::RecipientOverride(lock_id))
+    }
+
+    /// Re-checks an unsettled lock against the rules currently configured for
+    /// its destination: the chain registry (once any chain is registered), the
+    /// destination token registry (when validation is on) and a non-empty
+    /// recipient. Settled locks are never valid.
+    pub fn is_valid_for_chain(env: Env, lock_id: u64) -> bool {
+        let lock_data = read_lock(&env, lock_id);
+        if !is_unsettled(read_lock_status(&env, lock_id)) {
+            return false;
+        }
+
+        let supported_chains = read_supported_chains(&env);
+        if !supported_chains.is_empty() && !supported_chains.contains_key(lock_data.dest_chain) {
+            return false;
+        }
+
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        if config.validate_dest_token
+            && !env
+                .storage()
+                .instance()
+                .has(&DataKey::KnownDestToken(lock_data.dest_token))
+        {
+            return false;
+        }
+
+        !lock_data.recipient_address.is_empty()
+    }
+
+    /// The full history of one lock in a single call: its data, status, batch,
+    /// any recipient override and where its funds were settled.
+    pub fn get_lock_audit(env: Env, lock_id: u64) -> LockAudit {
+        let lock_data = read_lock(&env, lock_id);
+        let recipient_override: Option<RecipientOverride> = env
+            .storage()
+            .instance()
+            .get(&DataKey::RecipientOverride(lock_id));
+        let settlement: Option<LockSettlement> = env
+            .storage()
+            .instance()
+            .get(&DataKey::LockSettlement(lock_id));
+
+        LockAudit {
+            lock_data,
+            status: read_lock_status(&env, lock_id),
+            batch_id: env.storage().instance().get(&DataKey::LockBatch(lock_id)),
+            original_recipient: recipient_override
+                .clone()
+                .map(|recipient_override| recipient_override.original_recipient),
+            override_ledger: recipient_override.map(|recipient_override| recipient_override.ledger),
+            settled_ledger: settlement.clone().map(|settlement| settlement.ledger),
+            settled_to: settlement.clone().map(|settlement| settlement.settled_to),
+            settled_amount: settlement.map_or(0, |settlement| settlement.amount),
+            released_amount: read_released_amount(&env, lock_id),
+        }
+    }
+
+    pub fn status_counts(env: Env) -> StatusCounts {
+        read_status_counts(&env)
+    }
+
+    pub fn set_proof_verifier(env: Env, caller: Address, public_key: BytesN<32>) {
+        require_role_admin(&env, &caller);
+
+        env.storage()
+            .instance()
+            .set(&DataKey::ProofVerifier, &public_key);
+
+        let topics = ("ProofVerifierUpdatedEvent", ());
+        publish_event(&env, topics, public_key);
+    }
+
+    pub fn get_proof_verifier(env: Env) -> Option<BytesN<32>> {
+        env.storage().instance().get(&DataKey::ProofVerifier)
+    }
+
+    /// Releases a pending lock's `swaped_amount` to its recipient once the
+    /// configured oracle has signed `(contract, lock_id)`. No admin signature is
+    /// involved, so the funds are paid from liquidity held by the contract and
+    /// the recipient must be a Stellar strkey. The submitting `relayer` accrues
+    /// its share of the lock's fee.
+    pub fn release_with_inclusion_proof(env: Env, lock_id: u64, proof: Bytes, relayer: Address) {
+        // Check if contract is paused before proceeding
+        check_if_paused(&env);
+
+        // Set re-entrancy guard
+        check_and_set_reentrancy_guard(&env);
+
+        relayer.require_auth();
+
+        let public_key: BytesN<32> = match env.storage().instance().get(&DataKey::ProofVerifier) {
+            Some(public_key) => public_key,
+            None => panic_with_error!(&env, LockError::ProofVerifierNotSet),
+        };
+
+        read_lock(&env, lock_id);
+        check_releasable(&env, lock_id);
+
+        // Verify the oracle signature over the lock being released
+        let signature: BytesN<64> = match BytesN::try_from(&proof) {
+            Ok(signature) => signature,
+            Err(_) => panic_with_error!(&env, LockError::InvalidProof),
+        };
+        let message = (env.current_contract_address(), lock_id).to_xdr(&env);
+        env.crypto().ed25519_verify(&public_key, &message, &signature);
+
+        settle_from_custody(&env, lock_id, &relayer);
+
+        // Clear re-entrancy guard
+        clear_reentrancy_guard(&env);
+    }
+
+    /// Pre-authorizes a single custody release of `lock_id` that any relayer
+    /// may then submit through `release_authorized`.
+    pub fn authorize_release(env: Env, lock_id: u64) {
+        let lock_data = read_lock(&env, lock_id);
+        let admin = read_chain_admin(&env, &lock_data.dest_chain);
+        admin.require_auth();
+
+        check_releasable(&env, lock_id);
+        // A lone admin authorization only stands in for `SingleAdmin` chains
+        if read_release_scheme(&env, &lock_data.dest_chain) != ReleaseScheme::SingleAdmin {
+            panic_with_error!(&env, ExtraError::ReleaseNotAuthorized);
+        }
+        env.storage()
+            .instance()
+            .set(&ExtraKey::ReleaseAuth(lock_id), &());
+
+        let topics = ("ReleaseAuthorizedEvent", lock_id);
+        publish_event(&env, topics, admin);
+    }
+
+    /// Releases a lock the admin authorized with `authorize_release`, paying
+    /// its recipient out of custody like `release_with_inclusion_proof`. The
+    /// authorization is consumed, so the submission cannot be replayed.
+    pub fn release_authorized(env: Env, lock_id: u64, relayer: Address) {
+        // Check if contract is paused before proceeding
+        check_if_paused(&env);
+
+        // Set re-entrancy guard
+        check_and_set_reentrancy_guard(&env);
+
+        relayer.require_auth();
+
+        if !env.storage().instance().has(&ExtraKey::ReleaseAuth(lock_id)) {
+            panic_with_error!(&env, ExtraError::ReleaseNotAuthorized);
+        }
+        env.storage()
+            .instance()
+            .remove(&ExtraKey::ReleaseAuth(lock_id));
+        settle_from_custody(&env, lock_id, &relayer);
+
+        // Clear re-entrancy guard
+        clear_reentrancy_guard(&env);
+    }
+
+    /// Pays part of a lock's `swaped_amount` to its recipient out of custody.
+    /// Once the payouts add up to the full amount the lock becomes `Released`;
+    /// a released lock rejects any further release.
+    pub fn release_partial(env: Env, lock_id: u64, amount: i128) {
+        // Check if contract is paused before proceeding
+        check_if_paused(&env);
+
+        // Set re-entrancy guard
+        check_and_set_reentrancy_guard(&env);
+
+        let lock_data = read_lock(&env, lock_id);
+        read_chain_admin(&env, &lock_data.dest_chain).require_auth();
+
+        check_releasable(&env, lock_id);
+        let released_amount = read_released_amount(&env, lock_id);
+        if amount <= 0 || amount > lock_data.swaped_amount - released_amount {
+            panic_with_error!(&env, LockError::InvalidAmount);
+        }
+        check_lock_age(&env, lock_id, &lock_data);
+
+        let custodian = read_custodian(&env, &lock_data.from_token);
+        if available_liquidity(&env, &lock_data.from_token, &custodian, lock_id) < amount {
+            panic_with_error!(&env, LockError::InsufficientLiquidity);
+        }
+
+        let recipient = Address::from_string(&lock_data.recipient_address);
+        // Only the admin signs, which satisfies `SingleAdmin` chains alone
+        check_release_scheme(
+            &env,
+            lock_id,
+            amount,
+            &recipient,
+            &lock_data.from_token,
+            &ReleaseApproval::AdminOnly,
+        );
+        check_recipient_not_blocked(&env, &recipient);
+        check_release_cap(&env, &lock_data.from_token, amount);
+        record_recipient_release(&env, &recipient, amount);
+        add_released_today(&env, &lock_data.from_token, amount);
+        consume_held_principal(&env, lock_id, amount);
+        let released_amount = released_amount + amount;
+        env.storage()
+            .instance()
+            .set(&DataKey::ReleasedAmount(lock_id), &released_amount);
+        if released_amount == lock_data.swaped_amount {
+            complete_release(&env, lock_id, &recipient, released_amount);
+        }
+        let release_id = next_release_id(&env);
+        record_release(
+            &env,
+            release_id,
+            lock_id,
+            &recipient,
+            &lock_data.from_token,
+            amount,
+            &custodian,
+        );
+        record_activity(&env, true);
+
+        pay_from_custody(&env, &lock_data.from_token, &recipient, amount);
 
-        // Perform token transfer to the user
-        token::Client::new(&env, &destination_token).transfer(&admin_data.admin_address, &user, &amount);
-        
         // Publish release event
-        let topics = ("ReleaseEvent", user.clone(), destination_token.clone(), amount);
-        env.events().publish(topics, ());
+        let topics = (
+            "ReleaseEvent",
+            recipient.clone(),
+            lock_data.from_token.clone(),
+            amount,
+        );
+        publish_event(&env, topics, (release_id, lock_id));
+
+        notify_release_callback(&env, release_id, &recipient, &lock_data.from_token, amount);
+
+        // Clear re-entrancy guard
+        clear_reentrancy_guard(&env);
+    }
+
+    pub fn set_release_callback(env: Env, callback: Address) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        env.storage()
+            .instance()
+            .set(&DataKey::ReleaseCallback, &callback);
+
+        let topics = ("ReleaseCallbackSetEvent", callback);
+        publish_event(&env, topics, ());
+    }
+
+    pub fn remove_release_callback(env: Env) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        env.storage().instance().remove(&DataKey::ReleaseCallback);
+
+        let topics = ("ReleaseCallbackRemovedEvent", ());
+        publish_event(&env, topics, ());
+    }
+
+    pub fn get_release_callback(env: Env) -> Option<Address> {
+        env.storage().instance().get(&DataKey::ReleaseCallback)
+    }
+
+    pub fn set_admin_reserve(env: Env, token: Address, reserve: i128) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        if reserve < 0 {
+            panic_with_error!(&env, LockError::InvalidConfig);
+        }
+
+        env.storage()
+            .instance()
+            .set(&DataKey::AdminReserve(token.clone()), &reserve);
+
+        let topics = ("AdminReserveUpdatedEvent", token);
+        publish_event(&env, topics, reserve);
+    }
+
+    pub fn get_admin_reserve(env: Env, token: Address) -> i128 {
+        read_admin_reserve(&env, &token)
+    }
+
+    /// The largest `amount` a `release` of `token` could currently pay out:
+    /// the admin's balance above its reserve, bounded by what the daily
+    /// release cap still allows.
+    pub fn max_releasable(env: Env, token: Address) -> i128 {
+        let admin_data: AdminData = match env.storage().instance().get(&DataKey::Admin) {
+            Some(admin_data) => admin_data,
+            None => return 0,
+        };
+        let admin_balance = token::Client::new(&env, &token).balance(&admin_data.admin_address);
+        let releasable = (admin_balance - read_admin_reserve(&env, &token)).max(0);
+        let release_cap = read_release_cap(&env, &token);
+        if release_cap > 0 {
+            releasable.min((release_cap - read_released_today(&env, &token)).max(0))
+        } else {
+            releasable
+        }
+    }
+
+    pub fn set_recipient_lifetime_cap(env: Env, cap: i128) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        if cap < 0 {
+            panic_with_error!(&env, LockError::InvalidConfig);
+        }
+
+        let mut config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.recipient_lifetime_cap = cap;
+        env.storage().instance().set(&DataKey::Config, &config);
+
+        let topics = ("RecipientLifetimeCapUpdatedEvent", ());
+        publish_event(&env, topics, cap);
+    }
+
+    pub fn block_recipient(env: Env, recipient: Address) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        env.storage()
+            .instance()
+            .set(&DataKey::BlockedRecipient(recipient.clone()), &());
+
+        let topics = ("RecipientBlockedEvent", recipient);
+        publish_event(&env, topics, ());
+    }
+
+    pub fn unblock_recipient(env: Env, recipient: Address) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        env.storage()
+            .instance()
+            .remove(&DataKey::BlockedRecipient(recipient.clone()));
+
+        let topics = ("RecipientUnblockedEvent", recipient);
+        publish_event(&env, topics, ());
+    }
+
+    pub fn is_recipient_blocked(env: Env, recipient: Address) -> bool {
+        env.storage()
+            .instance()
+            .has(&DataKey::BlockedRecipient(recipient))
+    }
+
+    /// Marks `receiver` as a contract implementing `on_receive(token, amount) -> bool`,
+    /// which `release` calls after paying it.
+    pub fn register_receiver(env: Env, receiver: Address) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        env.storage()
+            .instance()
+            .set(&DataKey::ReceiverContract(receiver.clone()), &());
+
+        let topics = ("ReceiverRegisteredEvent", receiver);
+        publish_event(&env, topics, ());
+    }
+
+    pub fn unregister_receiver(env: Env, receiver: Address) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        env.storage()
+            .instance()
+            .remove(&DataKey::ReceiverContract(receiver.clone()));
+
+        let topics = ("ReceiverUnregisteredEvent", receiver);
+        publish_event(&env, topics, ());
+    }
+
+    pub fn is_registered_receiver(env: Env, receiver: Address) -> bool {
+        env.storage()
+            .instance()
+            .has(&DataKey::ReceiverContract(receiver))
+    }
+
+    pub fn get_recipient_lifetime_cap(env: Env) -> i128 {
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.recipient_lifetime_cap
+    }
+
+    pub fn get_recipient_released(env: Env, recipient: Address) -> i128 {
+        env.storage()
+            .instance()
+            .get(&DataKey::RecipientReleased(recipient))
+            .unwrap_or(0)
+    }
+
+    pub fn set_relayer_reimbursement_bps(env: Env, relayer_reimbursement_bps: i128) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        if !(0..=10_000).contains(&relayer_reimbursement_bps) {
+            panic_with_error!(&env, LockError::InvalidConfig);
+        }
+
+        let mut config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.relayer_reimbursement_bps = relayer_reimbursement_bps;
+        env.storage().instance().set(&DataKey::Config, &config);
+
+        let topics = ("RelayerReimbursementUpdatedEvent", ());
+        publish_event(&env, topics, relayer_reimbursement_bps);
+    }
+
+    /// Fee kept from user-initiated cancellations, capped at `MAX_REFUND_FEE_BPS`.
+    pub fn set_refund_fee_bps(env: Env, refund_fee_bps: i128) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        if !(0..=MAX_REFUND_FEE_BPS).contains(&refund_fee_bps) {
+            panic_with_error!(&env, LockError::InvalidFee);
+        }
+
+        let mut config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.refund_fee_bps = refund_fee_bps;
+        env.storage().instance().set(&DataKey::Config, &config);
+
+        let topics = ("RefundFeeUpdatedEvent", ());
+        publish_event(&env, topics, refund_fee_bps);
+    }
+
+    pub fn get_refund_fee_bps(env: Env) -> i128 {
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.refund_fee_bps
+    }
+
+    pub fn get_relayer_accrued(env: Env, relayer: Address, token: Address) -> i128 {
+        env.storage()
+            .instance()
+            .get(&DataKey::RelayerAccrued(relayer, token))
+            .unwrap_or(0)
+    }
+
+    pub fn claim_relayer_reimbursement(env: Env, relayer: Address, token: Address) -> i128 {
+        relayer.require_auth();
+
+        let key = DataKey::RelayerAccrued(relayer.clone(), token.clone());
+        let accrued: i128 = env.storage().instance().get(&key).unwrap_or(0);
+        if accrued < 1 {
+            panic_with_error!(&env, LockError::NothingToClaim);
+        }
+
+        env.storage().instance().remove(&key);
+        let owed = read_relayer_owed(&env, &token);
+        write_relayer_owed(&env, &token, owed - accrued);
+        pay_from_custody(&env, &token, &relayer, accrued);
+
+        let topics = ("RelayerReimbursedEvent", relayer, token);
+        publish_event(&env, topics, accrued);
+
+        accrued
+    }
+
+    /// Pays every token's accrued fees out to the current fee recipient, or to
+    /// the admin while none is set (as `flush_to_admin` would), then hands the
+    /// role to `new_recipient`. Fees collected before the change never reach
+    /// the new recipient.
+    pub fn change_fee_recipient(env: Env, caller: Address, new_recipient: Address) {
+        require_role_admin(&env, &caller);
+
+        let old_recipient: Option<Address> = env.storage().instance().get(&DataKey::FeeRecipient);
+        let old_payee = old_recipient.clone().or_else(|| {
+            env.storage()
+                .instance()
+                .get::<_, AdminData>(&DataKey::Admin)
+                .map(|admin_data| admin_data.admin_address)
+        });
+        if let Some(old_payee) = old_payee {
+            let tokens: Vec<Address> = env
+                .storage()
+                .instance()
+                .get(&DataKey::TokenRegistry)
+                .unwrap_or(Vec::new(&env));
+            for token in tokens.iter() {
+                let accrued = read_accrued_fees(&env, &token);
+                if accrued < 1 {
+                    continue;
+                }
+                write_accrued_fees(&env, &token, 0);
+                pay_from_custody(&env, &token, &old_payee, accrued);
+
+                let topics = ("FeesSweptEvent", old_payee.clone(), token);
+                publish_event(&env, topics, accrued);
+            }
+        }
+
+        env.storage().instance().set(&DataKey::FeeRecipient, &new_recipient);
+
+        let topics = ("FeeRecipientChangedEvent", new_recipient);
+        publish_event(&env, topics, old_recipient);
+    }
+
+    /// Pays the accrued fees of `token` in one transfer to the fee recipient,
+    /// or to the admin while no fee recipient is set. Returns the amount paid.
+    pub fn flush_to_admin(env: Env, token: Address) -> i128 {
+        let admin_data: AdminData = match env.storage().instance().get(&DataKey::Admin) {
+            Some(admin_data) => admin_data,
+            None => panic_with_error!(&env, LockError::AdminNotSet),
+        };
+        admin_data.admin_address.require_auth();
+
+        let accrued = read_accrued_fees(&env, &token);
+        if accrued < 1 {
+            panic_with_error!(&env, LockError::NothingToClaim);
+        }
+
+        let fee_payee: Address = env
+            .storage()
+            .instance()
+            .get(&DataKey::FeeRecipient)
+            .unwrap_or(admin_data.admin_address);
+        write_accrued_fees(&env, &token, 0);
+        pay_from_custody(&env, &token, &fee_payee, accrued);
+
+        let topics = ("FeesFlushedEvent", fee_payee, token);
+        publish_event(&env, topics, accrued);
+
+        accrued
+    }
+
+    /// Tries again to forward a lock fee that no payee accepted, paying it out
+    /// of custody to the fee recipient (or admin) and then the fallbacks.
+    /// Each lock gets `MAX_FORWARD_ATTEMPTS` attempts in total. Returns
+    /// whether the fee was delivered.
+    pub fn retry_forward(env: Env, lock_id: u64) -> bool {
+        let admin_data: AdminData = match env.storage().instance().get(&DataKey::Admin) {
+            Some(admin_data) => admin_data,
+            None => panic_with_error!(&env, LockError::AdminNotSet),
+        };
+        admin_data.admin_address.require_auth();
+
+        let mut forward_state: ForwardState = match env
+            .storage()
+            .instance()
+            .get(&ExtraKey::ForwardState(lock_id))
+        {
+            Some(forward_state) => forward_state,
+            None => panic_with_error!(&env, LockError::NothingToClaim),
+        };
+        let lock_data = read_lock(&env, lock_id);
+        // Refunded fees went back to the user, and flushed ones already left
+        // custody, so neither is left to forward
+        if forward_state.forwarded_to.is_some()
+            || read_lock_status(&env, lock_id) == LockStatus::Refunded
+            || read_accrued_fees(&env, &lock_data.from_token) < forward_state.fee
+        {
+            panic_with_error!(&env, LockError::NothingToClaim);
+        }
+        if forward_state.attempts >= MAX_FORWARD_ATTEMPTS {
+            panic_with_error!(&env, ExtraError::ForwardRetriesExhausted);
+        }
+
+        let custodian = read_custodian(&env, &lock_data.from_token);
+        if custodian != env.current_contract_address() {
+            custodian.require_auth();
+        }
+        let fee_payee: Address = env
+            .storage()
+            .instance()
+            .get(&DataKey::FeeRecipient)
+            .unwrap_or(admin_data.admin_address);
+        let token_client = token::Client::new(&env, &lock_data.from_token);
+        let forwarded = forward_fee(&env, &token_client, &custodian, fee_payee, forward_state.fee);
+
+        forward_state.attempts += 1;
+        forward_state.last_attempt_ledger = env.ledger().sequence();
+        if let Some(fee_payee) = &forwarded {
+            let accrued = read_accrued_fees(&env, &lock_data.from_token);
+            write_accrued_fees(&env, &lock_data.from_token, accrued - forward_state.fee);
+
+            // Refunds reclaim the fee from its recipient, as for a lock-time forward
+            let mut lock_data = lock_data;
+            lock_data.fee_payee = Some(fee_payee.clone());
+            env.storage()
+                .instance()
+                .set(&DataKey::Lock(lock_id), &lock_data);
+            forward_state.forwarded_to = forwarded.clone();
+        }
+        env.storage()
+            .instance()
+            .set(&ExtraKey::ForwardState(lock_id), &forward_state);
+
+        let topics = ("ForwardRetriedEvent", lock_id);
+        publish_event(&env, topics, forward_state);
+
+        forwarded.is_some()
+    }
+
+    pub fn get_forward_state(env: Env, lock_id: u64) -> Option<ForwardState> {
+        env.storage()
+            .instance()
+            .get(&ExtraKey::ForwardState(lock_id))
+    }
+
+    /// Lets the owner sweep the accrued fees of `token` to any address.
+    /// Returns the amount paid.
+    pub fn withdraw_fees(env: Env, token: Address, to: Address) -> i128 {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let accrued = read_accrued_fees(&env, &token);
+        if accrued < 1 {
+            panic_with_error!(&env, LockError::NothingToClaim);
+        }
+
+        write_accrued_fees(&env, &token, 0);
+        pay_from_custody(&env, &token, &to, accrued);
+
+        let topics = ("FeesWithdrawnEvent", to, token);
+        publish_event(&env, topics, accrued);
+
+        accrued
+    }
+
+    /// Rounding remainders collected for `token`, in `FEE_SCALE` units (one
+    /// token unit is `FEE_SCALE`).
+    pub fn get_protocol_remainder(env: Env, token: Address) -> i128 {
+        read_protocol_remainder(&env, &token)
+    }
+
+    /// Pays the whole units of `token` built up from rounding remainders to the
+    /// owner, keeping the fractional part. Returns the amount paid.
+    pub fn claim_remainder(env: Env, token: Address) -> i128 {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let protocol_remainder = read_protocol_remainder(&env, &token);
+        let claimable = protocol_remainder / FEE_SCALE;
+        if claimable < 1 {
+            panic_with_error!(&env, LockError::NothingToClaim);
+        }
+
+        env.storage().instance().set(
+            &DataKey::ProtocolRemainder(token.clone()),
+            &(protocol_remainder - claimable * FEE_SCALE),
+        );
+        pay_from_custody(&env, &token, &owner, claimable);
+
+        let topics = ("RemainderClaimedEvent", owner, token);
+        publish_event(&env, topics, claimable);
+
+        claimable
+    }
+
+    pub fn get_fee_recipient(env: Env) -> Option<Address> {
+        env.storage().instance().get(&DataKey::FeeRecipient)
+    }
+
+    /// Payees tried in order when a forwarded fee cannot be paid to the fee
+    /// recipient. If every one fails, the fee is held as accrued fees.
+    pub fn set_fee_recipient_fallbacks(env: Env, caller: Address, fallbacks: Vec<Address>) {
+        require_role_admin(&env, &caller);
+
+        if fallbacks.len() > MAX_QUERY_LEN {
+            panic_with_error!(&env, LockError::TooManyItems);
+        }
+
+        env.storage()
+            .instance()
+            .set(&ExtraKey::FeeRecipientFallbacks, &fallbacks);
+
+        let topics = ("FeeRecipientFallbacksUpdatedEvent", ());
+        publish_event(&env, topics, fallbacks);
+    }
+
+    pub fn get_fee_recipient_fallbacks(env: Env) -> Vec<Address> {
+        read_fee_recipient_fallbacks(&env)
+    }
+
+    pub fn get_accrued_fees(env: Env, token: Address) -> i128 {
+        read_accrued_fees(&env, &token)
+    }
+
+    pub fn get_fee_stats(env: Env) -> FeeStats {
+        read_fee_stats(&env)
+    }
+
+    pub fn get_user_stats(env: Env, user: Address) -> UserStats {
+        let mut stats = read_user_stats(&env, &user);
+        stats.total_released = env
+            .storage()
+            .instance()
+            .get(&DataKey::RecipientReleased(user))
+            .unwrap_or(0);
+        stats
+    }
+
+    /// Number of distinct addresses that have ever locked.
+    pub fn unique_user_count(env: Env) -> u64 {
+        env.storage()
+            .instance()
+            .get(&ExtraKey::UniqueUsers)
+            .unwrap_or(0)
+    }
+
+    /// Ids of every lock `user` has made, oldest first. Empty for a user that
+    /// never locked.
+    pub fn get_user_locks(env: Env, user: Address) -> Vec<u64> {
+        read_user_locks(&env, &user)
+    }
+
+    /// Lifetime fee charged per unit locked, in basis points. 0 before the
+    /// first lock.
+    pub fn average_fee_bps(env: Env) -> i128 {
+        let stats = read_fee_stats(&env);
+        if stats.total_in == 0 {
+            return 0;
+        }
+        match stats.total_fees.checked_mul(10_000) {
+            Some(scaled_fees) => scaled_fees / stats.total_in,
+            None => panic_with_error!(&env, LockError::AmountOverflow),
+        }
+    }
+
+    pub fn get_activity(env: Env) -> Activity {
+        read_activity(&env)
+    }
+
+    /// Every token that has been locked at least once, in order of first use.
+    pub fn list_known_tokens(env: Env) -> Vec<Address> {
+        env.storage()
+            .instance()
+            .get(&DataKey::TokenRegistry)
+            .unwrap_or(Vec::new(&env))
+    }
+
+    /// Operational counters as labeled pairs for metric scrapers. `tvl` and
+    /// `accrued_fees` are summed in raw units across every known token, with
+    /// `tvl` being what custody currently holds.
+    pub fn metrics(env: Env) -> Vec<(Symbol, i128)> {
+        let counts = read_status_counts(&env);
+        let total_locks: u64 = env
+            .storage()
+            .instance()
+            .get(&DataKey::LockCounter)
+            .unwrap_or(0);
+        let tokens: Vec<Address> = env
+            .storage()
+            .instance()
+            .get(&DataKey::TokenRegistry)
+            .unwrap_or(Vec::new(&env));
+
+        let mut tvl = 0;
+        let mut accrued_fees = 0;
+        for token in tokens.iter() {
+            let custodian = read_custodian(&env, &token);
+            tvl += token::Client::new(&env, &token).balance(&custodian);
+            accrued_fees += read_accrued_fees(&env, &token);
+        }
+
+        let pending = counts.pending_approval + counts.pending + counts.processing;
+        let mut metrics = Vec::new(&env);
+        metrics.push_back((Symbol::new(&env, "total_locks"), total_locks as i128));
+        metrics.push_back((Symbol::new(&env, "total_released"), counts.released as i128));
+        metrics.push_back((Symbol::new(&env, "total_refunded"), counts.refunded as i128));
+        metrics.push_back((Symbol::new(&env, "pending"), pending as i128));
+        metrics.push_back((Symbol::new(&env, "tvl"), tvl));
+        metrics.push_back((Symbol::new(&env, "accrued_fees"), accrued_fees));
+        metrics
+    }
+
+    /// Every scheduled governance change with the ledger it takes effect at.
+    /// A pending ownership transfer can be accepted right away, so it is listed
+    /// as effective from the current ledger.
+    pub fn get_pending_actions(env: Env) -> Vec<PendingAction> {
+        let mut actions = Vec::new(&env);
+        if env.storage().instance().has(&DataKey::PendingOwner) {
+            actions.push_back(PendingAction {
+                kind: Symbol::new(&env, "transfer_owner"),
+                effective_ledger: env.ledger().sequence(),
+            });
+        }
+        actions
+    }
+
+    pub fn get_limits(env: Env) -> Limits {
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+
+        Limits {
+            dup_window_ledgers: Some(config.dup_window_ledgers).filter(|limit| *limit > 0),
+            min_confirmations: Some(config.min_confirmations).filter(|limit| *limit > 0),
+            max_pending_per_user: Some(config.max_pending_per_user).filter(|limit| *limit > 0),
+            recipient_lifetime_cap: Some(config.recipient_lifetime_cap).filter(|limit| *limit > 0),
+            high_value_threshold: Some(config.high_value_threshold).filter(|limit| *limit > 0),
+            sunset_ledger: Some(config.sunset_ledger).filter(|limit| *limit > 0),
+            max_priority: MAX_PRIORITY,
+            max_query_len: MAX_QUERY_LEN,
+            max_allowed_tokens: MAX_ALLOWED_TOKENS,
+            max_batch_size: config.max_batch_size,
+            min_lock: config.min_lock,
+            max_lock: Some(config.max_lock).filter(|limit| *limit > 0),
+            min_lock_age_ledgers: Some(config.min_lock_age_ledgers).filter(|limit| *limit > 0),
+        }
+    }
+
+    /// Headroom left for `user` locking and receiving releases of `token`.
+    pub fn remaining_capacity(env: Env, token: Address, user: Address) -> Capacity {
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+
+        let pending_slots = if config.max_pending_per_user > 0 {
+            Some(config.max_pending_per_user.saturating_sub(read_pending_count(&env, &user)))
+        } else {
+            None
+        };
+        let recipient_lifetime = if config.recipient_lifetime_cap > 0 {
+            let released: i128 = env
+                .storage()
+                .instance()
+                .get(&DataKey::RecipientReleased(user))
+                .unwrap_or(0);
+            Some((config.recipient_lifetime_cap - released).max(0))
+        } else {
+            None
+        };
+
+        let release_cap = read_release_cap(&env, &token);
+        let daily_release = if release_cap > 0 {
+            Some((release_cap - read_released_today(&env, &token)).max(0))
+        } else {
+            None
+        };
+
+        Capacity {
+            releasable: Self::max_releasable(env, token),
+            pending_slots,
+            recipient_lifetime,
+            daily_release,
+        }
+    }
+
+    /// The contract's own balance of each token, in the order given.
+    pub fn balances(env: Env, tokens: Vec<Address>) -> Vec<i128> {
+        if tokens.len() > MAX_QUERY_LEN {
+            panic_with_error!(&env, LockError::TooManyItems);
+        }
+
+        let mut balances = Vec::new(&env);
+        for token in tokens.iter() {
+            balances.push_back(token::Client::new(&env, &token).balance(&env.current_contract_address()));
+        }
+        balances
+    }
+
+    /// Caps how much of `token` may be released per UTC day, across admin and
+    /// custody releases alike. Zero removes the cap.
+    pub fn set_release_cap(env: Env, token: Address, cap: i128) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        if cap < 0 {
+            panic_with_error!(&env, LockError::InvalidAmount);
+        }
+        env.storage()
+            .instance()
+            .set(&ExtraKey::ReleaseCap(token.clone()), &cap);
+
+        let topics = ("ReleaseCapUpdatedEvent", token);
+        publish_event(&env, topics, cap);
+    }
+
+    pub fn get_release_cap(env: Env, token: Address) -> i128 {
+        read_release_cap(&env, &token)
+    }
+
+    /// Runs every check `release` would without moving funds. Returns true when
+    /// the release would succeed and panics with the failing check's error otherwise.
+    pub fn dry_run_release(
+        env: Env,
+        admin: Address,
+        lock_id: u64,
+        amount: i128,
+        user: Address,
+        destination_token: Address,
+    ) -> bool {
+        check_if_paused(&env);
+        validate_release(&env, &admin, lock_id, amount, &user, &destination_token);
+        check_release_scheme(
+            &env,
+            lock_id,
+            amount,
+            &user,
+            &destination_token,
+            &ReleaseApproval::AdminOnly,
+        );
+        true
+    }
+
+    /// Pays `amount` of `destination_token` to `user` from `admin`'s balance
+    /// against lock `lock_id`. Any registered admin may release. Releases add up
+    /// to at most the lock's `swaped_amount`, at which point the lock becomes
+    /// `Released` and further releases fail with `AlreadyReleased`. Returns
+    /// true once the transfer is made; every failure panics.
+    pub fn release(
+        env: Env,
+        admin: Address,
+        lock_id: u64,
+        amount: i128,
+        user: Address,
+        destination_token: Address,
+    ) -> bool {
+        // Check if contract is paused before proceeding
+        check_if_paused(&env);
+
+        // Set re-entrancy guard
+        check_and_set_reentrancy_guard(&env);
         
+        // Admin authorization and validation
+        admin.require_auth();
+        execute_release(
+            &env,
+            &admin,
+            lock_id,
+            amount,
+            &user,
+            &destination_token,
+            &ReleaseApproval::AdminOnly,
+        );
+
+        // Clear re-entrancy guard
+        clear_reentrancy_guard(&env);
+        true
+    }
+
+    /// `release` for locks whose destination chain uses a `Multisig` or
+    /// `OracleSigned` scheme, carrying the approval that scheme requires.
+    pub fn release_with_approval(
+        env: Env,
+        admin: Address,
+        lock_id: u64,
+        amount: i128,
+        user: Address,
+        destination_token: Address,
+        approval: ReleaseApproval,
+    ) -> bool {
+        // Check if contract is paused before proceeding
+        check_if_paused(&env);
+
+        // Set re-entrancy guard
+        check_and_set_reentrancy_guard(&env);
+
+        admin.require_auth();
+        execute_release(&env, &admin, lock_id, amount, &user, &destination_token, &approval);
+
+        // Clear re-entrancy guard
+        clear_reentrancy_guard(&env);
+        true
+    }
+
+    /// Runs several releases from `admin`'s balance under one authorization.
+    /// The admin must hold enough of each token for the whole batch up front,
+    /// and any failing entry reverts the entire batch. Locks bound for chains
+    /// with a stricter release scheme need `release_with_approval`.
+    pub fn release_batch(env: Env, admin: Address, releases: Vec<ReleaseRequest>) {
+        // Check if contract is paused before proceeding
+        check_if_paused(&env);
+
+        // Set re-entrancy guard
+        check_and_set_reentrancy_guard(&env);
+
+        admin.require_auth();
+        if releases.is_empty() {
+            panic_with_error!(&env, LockError::EmptyBatch);
+        }
+        check_batch_size(&env, releases.len());
+
+        // Check the admin covers every token's total before paying anything
+        let mut totals: Map<Address, i128> = Map::new(&env);
+        for request in releases.iter() {
+            let total = totals.get(request.destination_token.clone()).unwrap_or(0);
+            match total.checked_add(request.amount) {
+                Some(total) => totals.set(request.destination_token, total),
+                None => panic_with_error!(&env, LockError::AmountOverflow),
+            }
+        }
+        for (token, total) in totals.iter() {
+            let available = token::Client::new(&env, &token).balance(&admin)
+                - read_admin_reserve(&env, &token);
+            if available < total {
+                panic_with_error!(&env, LockError::InsufficientAdminBalance);
+            }
+        }
+
+        for request in releases.iter() {
+            execute_release(
+                &env,
+                &admin,
+                request.lock_id,
+                request.amount,
+                &request.user,
+                &request.destination_token,
+                &ReleaseApproval::AdminOnly,
+            );
+        }
+
+        // Clear re-entrancy guard
+        clear_reentrancy_guard(&env);
+    }
+
+    /// Returns the lock ids from `ids` that have not been settled yet, in the
+    /// order given, so a relayer can drop finished entries from a
+    /// `release_batch` before submitting it. Unknown ids are dropped too.
+    pub fn filter_unprocessed_releases(env: Env, ids: Vec<u64>) -> Vec<u64> {
+        if ids.len() > MAX_QUERY_LEN {
+            panic_with_error!(&env, LockError::TooManyItems);
+        }
+
+        let mut unprocessed = Vec::new(&env);
+        for lock_id in ids.iter() {
+            if lock_exists(&env, lock_id) && is_unsettled(read_lock_status(&env, lock_id)) {
+                unprocessed.push_back(lock_id);
+            }
+        }
+        unprocessed
+    }
+
+    /// Releases may be reversed by the owner for this many ledgers after they
+    /// happen. Zero disables reversals.
+    pub fn set_dispute_window(env: Env, dispute_window_ledgers: u32) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let mut config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.dispute_window_ledgers = dispute_window_ledgers;
+        env.storage().instance().set(&DataKey::Config, &config);
+
+        let topics = ("DisputeWindowUpdatedEvent", ());
+        publish_event(&env, topics, dispute_window_ledgers);
+    }
+
+    pub fn get_dispute_window(env: Env) -> u32 {
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.dispute_window_ledgers
+    }
+
+    /// Caps how many locks one batch call may touch, up to `MAX_QUERY_LEN`.
+    pub fn set_max_batch_size(env: Env, max_batch_size: u32) {
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        if !(1..=MAX_QUERY_LEN).contains(&max_batch_size) {
+            panic_with_error!(&env, LockError::InvalidConfig);
+        }
+
+        let mut config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.max_batch_size = max_batch_size;
+        env.storage().instance().set(&DataKey::Config, &config);
+
+        let topics = ("MaxBatchSizeUpdatedEvent", ());
+        publish_event(&env, topics, max_batch_size);
+    }
+
+    pub fn get_max_batch_size(env: Env) -> u32 {
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.max_batch_size
+    }
+
+    pub fn get_release(env: Env, release_id: u64) -> ReleaseRecord {
+        match env.storage().instance().get(&DataKey::Release(release_id)) {
+            Some(release) => release,
+            None => panic_with_error!(&env, LockError::ReleaseNotFound),
+        }
+    }
+
+    /// Claws a disputed release back from its recipient to where the funds came
+    /// from. The recipient must still hold the amount and must have approved
+    /// this contract to spend it, since a token transfer cannot be forced.
+    /// The lock owes the amount again: a released lock goes back to `Pending`.
+    pub fn reverse_release(env: Env, release_id: u64) {
+        // Set re-entrancy guard
+        check_and_set_reentrancy_guard(&env);
+
+        let owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        owner.require_auth();
+
+        let mut release = Self::get_release(env.clone(), release_id);
+        let config: Config = env.storage().instance().get(&DataKey::Config).unwrap();
+        if release.reversed
+            || config.dispute_window_ledgers == 0
+            || env.ledger().sequence() >= release.ledger.saturating_add(config.dispute_window_ledgers)
+        {
+            panic_with_error!(&env, LockError::ReversalNotAllowed);
+        }
+
+        let token_client = token::Client::new(&env, &release.token);
+        if token_client.balance(&release.recipient) < release.amount {
+            panic_with_error!(&env, LockError::RecipientFundsMissing);
+        }
+
+        // Update state before external interactions
+        release.reversed = true;
+        env.storage()
+            .instance()
+            .set(&DataKey::Release(release_id), &release);
+        let released: i128 = env
+            .storage()
+            .instance()
+            .get(&DataKey::RecipientReleased(release.recipient.clone()))
+            .unwrap_or(0);
+        env.storage().instance().set(
+            &DataKey::RecipientReleased(release.recipient.clone()),
+            &(released - release.amount).max(0),
+        );
+
+        // The lock owes the reversed amount again
+        let released_amount = read_released_amount(&env, release.lock_id) - release.amount;
+        env.storage()
+            .instance()
+            .set(&DataKey::ReleasedAmount(release.lock_id), &released_amount);
+        let previous = read_lock_status(&env, release.lock_id);
+        if previous == LockStatus::Released {
+            write_lock_status(&env, release.lock_id, LockStatus::Pending);
+            env.storage()
+                .instance()
+                .remove(&DataKey::LockSettlement(release.lock_id));
+            let topics = ("LockStatusChangedEvent", release.lock_id);
+            publish_event(&env, topics, (previous, LockStatus::Pending));
+        }
+        // Only a release made today still counts against today's cap
+        if release.timestamp / 86_400 == env.ledger().timestamp() / 86_400 {
+            let volume = read_released_today(&env, &release.token) - release.amount;
+            env.storage().instance().set(
+                &ExtraKey::ReleasedToday(release.token.clone()),
+                &DailyVolume {
+                    day: env.ledger().timestamp() / 86_400,
+                    volume: volume.max(0),
+                },
+            );
+        }
+
+        token_client.transfer_from(
+            &env.current_contract_address(),
+            &release.recipient,
+            &release.source,
+            &release.amount,
+        );
+
+        let topics = ("ReleaseReversedEvent", release_id, release.recipient);
+        publish_event(&env, topics, release.amount);
+
         // Clear re-entrancy guard
         clear_reentrancy_guard(&env);
     }
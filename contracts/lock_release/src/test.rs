@@ -1,17 +1,3485 @@
 #![cfg(test)]
 
 use super::*;
-use soroban_sdk::{symbol_short, vec, Env};
+use ed25519_dalek::{Signer, SigningKey};
+use soroban_sdk::{
+    contract, symbol_short, vec,
+    testutils::{Address as _, Events, IssuerFlags, Ledger},
+    token::StellarAssetClient,
+    Env, TryFromVal,
+};
+
+fn setup(env: &Env) -> (LockAndReleaseContractClient<'_>, Address, Address, Address) {
+    env.mock_all_auths();
+
+    let contract_id = env.register_contract(None, LockAndReleaseContract);
+    let client = LockAndReleaseContractClient::new(env, &contract_id);
+
+    let owner = Address::generate(env);
+    let admin = Address::generate(env);
+    client.initialize(&owner, &3, &0, &1, &0);
+    client.add_admin(&owner, &admin);
+
+    let token = env.register_stellar_asset_contract_v2(owner.clone()).address();
+
+    (client, owner, admin, token)
+}
+
+fn fund(env: &Env, token: &Address, to: &Address, amount: i128) {
+    StellarAssetClient::new(env, token).mint(to, &amount);
+}
+
+fn has_event(env: &Env, name: &str) -> bool {
+    let name = String::from_str(env, name);
+    env.events().all().iter().any(|(_, topics, _)| {
+        String::try_from_val(env, &topics.get(0).unwrap()) == Ok(name.clone())
+    })
+}
+
+fn lock_default(
+    env: &Env,
+    client: &LockAndReleaseContractClient,
+    user: &Address,
+    token: &Address,
+    in_amount: i128,
+) -> u64 {
+    client.lock(
+        user,
+        token,
+        &String::from_str(env, "USDC"),
+        &in_amount,
+        &Bytes::from_slice(env, b"eth"),
+        &String::from_str(env, "0xabc"),
+        &false,
+        &None,
+        &None,
+        &0,
+    )
+}
+
+// Imports a pending lock for admin releases to settle against, without moving
+// any funds. Its `swaped_amount` covers every release a test makes.
+fn import_lock_for_release(env: &Env, client: &LockAndReleaseContractClient, token: &Address) -> u64 {
+    let lock_id = 1_000;
+    let lock_data = LockData {
+        user_address: Address::generate(env),
+        dest_token: String::from_str(env, "USDC"),
+        from_token: token.clone(),
+        in_amount: 10_000,
+        swaped_amount: 10_000,
+        recipient_address: String::from_str(env, "0xabc"),
+        dest_chain: Bytes::from_slice(env, b"eth"),
+        created_ledger: 0,
+        refund_fallback: None,
+        fee_scaled_applied: 0,
+        memo: None,
+        priority: 0,
+        remainder_charged: 0,
+        fee_payee: None,
+    };
+    client.import_lock(&lock_id, &lock_data, &LockStatus::Pending);
+    lock_id
+}
+
+#[test]
+fn test_duplicate_lock_rejected_within_window() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    client.set_dup_window(&10);
+    assert_eq!(client.get_dup_window(), 10);
+
+    let dest_token = String::from_str(&env, "USDC");
+    let dest_chain = Bytes::from_slice(&env, b"eth");
+    let recipient = String::from_str(&env, "0xabc");
+
+    client.lock(&user, &token, &dest_token, &100, &dest_chain, &recipient, &false, &None, &None, &0);
+    let res = client.try_lock(&user, &token, &dest_token, &100, &dest_chain, &recipient, &false, &None, &None, &0);
+    assert_eq!(res, Err(Ok(LockError::DuplicateLock.into())));
+
+    // An explicit force bypasses the check, and the window expires on its own
+    client.lock(&user, &token, &dest_token, &100, &dest_chain, &recipient, &true, &None, &None, &0);
+    env.ledger().with_mut(|li| li.sequence_number += 10);
+    client.lock(&user, &token, &dest_token, &100, &dest_chain, &recipient, &false, &None, &None, &0);
+}
+
+#[test]
+fn test_override_recipient_records_original() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    let lock_id = lock_default(&env, &client, &user, &token, 100);
+    assert!(client.get_recipient_override(&lock_id).is_none());
+
+    client.override_recipient(&lock_id, &String::from_str(&env, "0xdef"));
+    client.override_recipient(&lock_id, &String::from_str(&env, "0x123"));
+
+    let record = client.get_recipient_override(&lock_id).unwrap();
+    assert_eq!(record.original_recipient, String::from_str(&env, "0xabc"));
+}
+
+#[test]
+fn test_status_counts_track_new_locks() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    assert_eq!(client.status_counts().pending, 0);
+    for amount in [100, 200] {
+        lock_default(&env, &client, &user, &token, amount);
+    }
+
+    let counts = client.status_counts();
+    assert_eq!(counts.pending, 2);
+    assert_eq!(counts.released, 0);
+    assert_eq!(counts.refunded, 0);
+}
+
+fn sign_lock(env: &Env, signing_key: &SigningKey, contract: &Address, lock_id: u64) -> Bytes {
+    let message = (contract.clone(), lock_id).to_xdr(env);
+    let mut message_buf = [0u8; 128];
+    let message_len = message.len() as usize;
+    message.copy_into_slice(&mut message_buf[..message_len]);
+    Bytes::from_slice(env, &signing_key.sign(&message_buf[..message_len]).to_bytes())
+}
+
+#[test]
+fn test_release_with_inclusion_proof() {
+    let env = Env::default();
+    let (client, owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    fund(&env, &token, &client.address, 1_000);
+
+    let signing_key = SigningKey::from_bytes(&[7; 32]);
+    client.set_proof_verifier(&owner, &BytesN::from_array(
+        &env,
+        &signing_key.verifying_key().to_bytes(),
+    ));
+
+    let lock_id = client.lock(
+        &user,
+        &token,
+        &String::from_str(&env, "USDC"),
+        &100,
+        &Bytes::from_slice(&env, b"eth"),
+        &recipient.to_string(),
+        &false,
+        &None,
+        &None,
+        &0,
+    );
+
+    let signature = sign_lock(&env, &signing_key, &client.address, lock_id);
+
+    // An invalid signature is rejected
+    let bad_proof = Bytes::from_slice(&env, &[0; 64]);
+    let relayer = Address::generate(&env);
+    assert!(client
+        .try_release_with_inclusion_proof(&lock_id, &bad_proof, &relayer)
+        .is_err());
+
+    client.release_with_inclusion_proof(&lock_id, &signature, &relayer);
+    assert_eq!(token::Client::new(&env, &token).balance(&recipient), 97);
+    assert_eq!(client.status_counts().released, 1);
+
+    // The lock can only be released once
+    assert!(client
+        .try_release_with_inclusion_proof(&lock_id, &signature, &relayer)
+        .is_err());
+}
+
+#[test]
+fn test_chain_name_registry() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    let name = String::from_str(&env, "ethereum");
+    let dest_chain = Bytes::from_slice(&env, &[0, 0, 0, 1]);
+    client.set_chain_name(&name, &dest_chain);
+    assert_eq!(client.resolve_chain(&name), dest_chain);
+    assert_eq!(client.chain_name(&dest_chain), name);
+
+    client.lock_to_chain(
+        &user,
+        &token,
+        &String::from_str(&env, "USDC"),
+        &100,
+        &name,
+        &String::from_str(&env, "0xabc"),
+        &false,
+        &None,
+        &None,
+        &0,
+    );
+
+    // Renaming the chain retires its old name
+    let renamed = String::from_str(&env, "mainnet");
+    client.set_chain_name(&renamed, &dest_chain);
+    assert!(client.try_resolve_chain(&name).is_err());
+    assert_eq!(client.resolve_chain(&renamed), dest_chain);
+    assert_eq!(client.chain_name(&dest_chain), renamed);
+
+    client.remove_chain_name(&renamed);
+    assert!(client.try_resolve_chain(&renamed).is_err());
+    assert!(client.try_chain_name(&dest_chain).is_err());
+}
+
+#[contract]
+struct ReleaseRecorder;
+
+#[contractimpl]
+impl ReleaseRecorder {
+    pub fn set_reject(env: Env, reject: bool) {
+        env.storage().instance().set(&symbol_short!("reject"), &reject);
+    }
+
+    pub fn on_release(env: Env, release_id: u64, _user: Address, _token: Address, amount: i128) {
+        if env
+            .storage()
+            .instance()
+            .get(&symbol_short!("reject"))
+            .unwrap_or(false)
+        {
+            panic!("rejected");
+        }
+        env.storage().instance().set(&release_id, &amount);
+    }
+
+    pub fn recorded(env: Env, release_id: u64) -> Option<i128> {
+        env.storage().instance().get(&release_id)
+    }
+
+    pub fn on_receive(env: Env, _token: Address, amount: i128) -> bool {
+        let reject: bool = env
+            .storage()
+            .instance()
+            .get(&symbol_short!("reject"))
+            .unwrap_or(false);
+        if !reject {
+            env.storage().instance().set(&symbol_short!("received"), &amount);
+        }
+        !reject
+    }
+
+    pub fn received(env: Env) -> Option<i128> {
+        env.storage().instance().get(&symbol_short!("received"))
+    }
+}
+
+#[test]
+fn test_release_callback_notified_and_failures_tolerated() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let lock_id = import_lock_for_release(&env, &client, &token);
+    let user = Address::generate(&env);
+    fund(&env, &token, &admin, 1_000);
+
+    let recorder_id = env.register_contract(None, ReleaseRecorder);
+    let recorder = ReleaseRecorderClient::new(&env, &recorder_id);
+    client.set_release_callback(&recorder_id);
+    assert_eq!(client.get_release_callback(), Some(recorder_id.clone()));
+
+    client.release(&admin, &lock_id, &150, &user, &token);
+    assert_eq!(recorder.recorded(&0), Some(150));
+
+    // A reverting callback must not undo the release itself
+    recorder.set_reject(&true);
+    client.release(&admin, &lock_id, &50, &user, &token);
+    assert_eq!(recorder.recorded(&1), None);
+    assert_eq!(token::Client::new(&env, &token).balance(&user), 200);
+}
+
+#[test]
+fn test_max_releasable_respects_reserve() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let lock_id = import_lock_for_release(&env, &client, &token);
+    let user = Address::generate(&env);
+    fund(&env, &token, &admin, 1_000);
+
+    assert_eq!(client.max_releasable(&token), 1_000);
+    client.set_admin_reserve(&token, &400);
+    assert_eq!(client.max_releasable(&token), 600);
+
+    assert!(client.try_release(&admin, &lock_id, &601, &user, &token).is_err());
+    client.release(&admin, &lock_id, &600, &user, &token);
+    assert_eq!(client.max_releasable(&token), 0);
+}
+
+#[test]
+fn test_min_confirmations_gate_processing() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    client.set_min_confirmations(&5);
+    assert_eq!(client.get_min_confirmations(), 5);
+
+    let lock_id = lock_default(&env, &client, &user, &token, 100);
+    assert_eq!(client.pending_locks(&0, &10).len(), 0);
+    assert!(client.try_mark_processing(&lock_id).is_err());
+
+    env.ledger().with_mut(|li| li.sequence_number += 5);
+    assert_eq!(client.pending_locks(&0, &10), vec![&env, lock_id]);
+    client.mark_processing(&lock_id);
+    assert_eq!(client.status_counts().processing, 1);
+    assert_eq!(client.pending_locks(&0, &10).len(), 0);
+}
+
+#[test]
+fn test_get_locks_by_ids() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    for amount in [100, 200, 300] {
+        lock_default(&env, &client, &user, &token, amount);
+    }
+
+    let locks = client.get_locks_by_ids(&vec![&env, 2, 0]);
+    assert_eq!(locks.len(), 2);
+    assert_eq!(locks.get(0).unwrap().in_amount, 300);
+    assert_eq!(locks.get(1).unwrap().in_amount, 100);
+
+    assert!(client.try_get_locks_by_ids(&vec![&env, 0, 3]).is_err());
+}
+
+#[test]
+fn test_zero_fee_lock_skips_contract_custody() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    client.set_token_config(
+        &token,
+        &TokenConfig {
+            fee_scaled: Some(0),
+            paused: false,
+            decimals: 7,
+            low_balance_threshold: 0,
+            min_swaped: 1,
+        },
+    );
+    lock_default(&env, &client, &user, &token, 500);
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&admin), 500);
+    assert_eq!(token_client.balance(&client.address), 0);
+    assert_eq!(token_client.balance(&user), 500);
+}
+
+#[test]
+fn test_recipient_lifetime_cap() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let lock_id = import_lock_for_release(&env, &client, &token);
+    let user = Address::generate(&env);
+    fund(&env, &token, &admin, 1_000);
+
+    client.set_recipient_lifetime_cap(&300);
+    assert_eq!(client.get_recipient_lifetime_cap(), 300);
+
+    client.release(&admin, &lock_id, &200, &user, &token);
+    client.release(&admin, &lock_id, &100, &user, &token);
+    assert_eq!(client.get_recipient_released(&user), 300);
+
+    let res = client.try_release(&admin, &lock_id, &1, &user, &token);
+    assert_eq!(res, Err(Ok(LockError::RecipientCapExceeded.into())));
+}
+
+#[test]
+fn test_low_balance_event_emitted_below_threshold() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let lock_id = import_lock_for_release(&env, &client, &token);
+    let user = Address::generate(&env);
+    fund(&env, &token, &admin, 1_000);
+
+    client.set_token_config(
+        &token,
+        &TokenConfig {
+            fee_scaled: None,
+            paused: false,
+            decimals: 7,
+            low_balance_threshold: 500,
+            min_swaped: 1,
+        },
+    );
+
+    client.release(&admin, &lock_id, &400, &user, &token);
+    assert!(!has_event(&env, "LowBalanceEvent"));
+
+    client.release(&admin, &lock_id, &200, &user, &token);
+    assert!(has_event(&env, "LowBalanceEvent"));
+}
+
+#[test]
+fn test_import_lock_until_migration_finalized() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    let imported = LockData {
+        user_address: user.clone(),
+        dest_token: String::from_str(&env, "USDC"),
+        from_token: token.clone(),
+        in_amount: 1_000,
+        swaped_amount: 970,
+        recipient_address: String::from_str(&env, "0xabc"),
+        dest_chain: Bytes::from_slice(&env, b"eth"),
+        created_ledger: 0,
+        refund_fallback: None,
+        fee_scaled_applied: 30_000,
+        memo: None,
+        priority: 0,
+        remainder_charged: 0,
+        fee_payee: None,
+    };
+    client.import_lock(&41, &imported, &LockStatus::Processing);
+    assert!(client
+        .try_import_lock(&41, &imported, &LockStatus::Pending)
+        .is_err());
+    assert_eq!(client.status_counts().processing, 1);
+
+    // New locks are numbered after the highest imported id
+    let lock_id = lock_default(&env, &client, &user, &token, 100);
+    assert_eq!(lock_id, 42);
+
+    // Scans skip the ids below the import that were never assigned
+    assert_eq!(client.pending_locks(&0, &10), vec![&env, 42]);
+    assert_eq!(client.get_locks_by_priority(&0, &0, &10), vec![&env, 41, 42]);
+    assert_eq!(client.get_user_locks(&user), vec![&env, 41, 42]);
+    assert_eq!(client.list_known_tokens(), vec![&env, token.clone()]);
+
+    // An import cannot date a lock after the current ledger
+    let mut future = imported.clone();
+    future.created_ledger = env.ledger().sequence() + 1;
+    assert_eq!(
+        client.try_import_lock(&50, &future, &LockStatus::Pending),
+        Err(Ok(ExtraError::InvalidCreatedLedger.into()))
+    );
+
+    client.finalize_migration();
+    assert!(client.is_migration_complete());
+    assert!(client
+        .try_import_lock(&7, &imported, &LockStatus::Pending)
+        .is_err());
+}
+
+#[test]
+fn test_relayer_reimbursement_accrues_on_proof_release() {
+    let env = Env::default();
+    let (client, owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    let relayer = Address::generate(&env);
+    fund(&env, &token, &user, 2_000);
+    fund(&env, &token, &client.address, 2_000);
+
+    let signing_key = SigningKey::from_bytes(&[7; 32]);
+    client.set_proof_verifier(&owner, &BytesN::from_array(
+        &env,
+        &signing_key.verifying_key().to_bytes(),
+    ));
+    client.set_relayer_reimbursement_bps(&5_000);
+
+    let lock_id = client.lock(
+        &user,
+        &token,
+        &String::from_str(&env, "USDC"),
+        &1_000,
+        &Bytes::from_slice(&env, b"eth"),
+        &Address::generate(&env).to_string(),
+        &false,
+        &None,
+        &None,
+        &0,
+    );
+    let proof = sign_lock(&env, &signing_key, &client.address, lock_id);
+    client.release_with_inclusion_proof(&lock_id, &proof, &relayer);
+
+    // Half of the 30 fee goes to the relayer that submitted the release
+    assert_eq!(client.get_relayer_accrued(&relayer, &token), 15);
+    assert_eq!(client.claim_relayer_reimbursement(&relayer, &token), 15);
+    assert_eq!(token::Client::new(&env, &token).balance(&relayer), 15);
+    assert!(client
+        .try_claim_relayer_reimbursement(&relayer, &token)
+        .is_err());
+
+    // A forwarded fee never accrued, so other locks' fees do not pay for it
+    client.set_forward_dust_threshold(&1);
+    let forwarded_id = client.lock(
+        &user,
+        &token,
+        &String::from_str(&env, "USDC"),
+        &1_000,
+        &Bytes::from_slice(&env, b"eth"),
+        &Address::generate(&env).to_string(),
+        &false,
+        &None,
+        &None,
+        &0,
+    );
+    assert!(client.get_lock_audit(&forwarded_id).lock_data.fee_payee.is_some());
+    let proof = sign_lock(&env, &signing_key, &client.address, forwarded_id);
+    client.release_with_inclusion_proof(&forwarded_id, &proof, &relayer);
+    assert_eq!(client.get_relayer_accrued(&relayer, &token), 0);
+    assert_eq!(client.get_accrued_fees(&token), 15);
+}
+
+#[test]
+fn test_global_seq_orders_all_events() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    fund(&env, &token, &admin, 1_000);
+
+    // `initialize` and `add_admin` during setup emitted the first events
+    assert_eq!(client.get_global_seq(), 2);
+
+    let lock_id = lock_default(&env, &client, &user, &token, 100);
+    let (_, _, data) = env.events().all().last().unwrap();
+    let (seq, _): (u64, (LockData, BytesN<32>)) =
+        <(u64, (LockData, BytesN<32>))>::try_from_val(&env, &data).unwrap();
+    assert_eq!(seq, 3);
+
+    client.release(&admin, &lock_id, &50, &user, &token);
+    assert_eq!(client.get_global_seq(), 4);
+}
+
+#[test]
+fn test_dynamic_fee_scales_with_daily_volume() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 100_000);
+
+    client.set_dynamic_fee(&DynamicFee {
+        min_fee_bps: 10,
+        max_fee_bps: 110,
+        target_daily_volume: 20_000,
+    });
+
+    let lock = |amount: i128| {
+        client.lock(
+            &user,
+            &token,
+            &String::from_str(&env, "USDC"),
+            &amount,
+            &Bytes::from_slice(&env, b"eth"),
+            &String::from_str(&env, "0xabc"),
+            &true,
+            &None,
+            &None,
+            &0,
+        )
+    };
+    let token_client = token::Client::new(&env, &token);
+
+    // No volume yet: minimum fee of 10 bps
+    lock(10_000);
+    assert_eq!(token_client.balance(&admin), 9_990);
+
+    // Half the target: 60 bps
+    lock(10_000);
+    assert_eq!(token_client.balance(&admin), 9_990 + 9_940);
+
+    // At or above the target: capped at 110 bps
+    lock(10_000);
+    assert_eq!(token_client.balance(&admin), 9_990 + 9_940 + 9_890);
+    assert_eq!(client.get_daily_volume(&token), 30_000);
+
+    // Volume resets on the next day
+    env.ledger().with_mut(|li| li.timestamp += 86_400);
+    assert_eq!(client.get_daily_volume(&token), 0);
+}
+
+#[test]
+fn test_balances_reports_contract_holdings() {
+    let env = Env::default();
+    let (client, owner, _admin, token) = setup(&env);
+    let other_token = env.register_stellar_asset_contract_v2(owner).address();
+    fund(&env, &token, &client.address, 300);
+
+    assert_eq!(
+        client.balances(&vec![&env, token, other_token]),
+        vec![&env, 300, 0]
+    );
+}
+
+#[test]
+fn test_refund_falls_back_when_user_cannot_receive() {
+    let env = Env::default();
+    let (client, owner, _admin, _token) = setup(&env);
+    let asset = env.register_stellar_asset_contract_v2(owner);
+    asset.issuer().set_flag(IssuerFlags::RevocableFlag);
+    let token = asset.address();
+    let user = Address::generate(&env);
+    let fallback = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    let refunded_id = lock_default(&env, &client, &user, &token, 100);
+    client.refund(&refunded_id);
+    assert_eq!(token::Client::new(&env, &token).balance(&user), 1_000);
+    assert!(client.try_refund(&refunded_id).is_err());
+
+    let lock_id = client.lock(
+        &user,
+        &token,
+        &String::from_str(&env, "USDC"),
+        &200,
+        &Bytes::from_slice(&env, b"eth"),
+        &String::from_str(&env, "0xabc"),
+        &false,
+        &Some(fallback.clone()),
+        &None,
+        &0,
+    );
+
+    // The user's trustline is revoked, so the refund is routed to the fallback
+    StellarAssetClient::new(&env, &token).set_authorized(&user, &false);
+    client.refund(&lock_id);
+    assert_eq!(token::Client::new(&env, &token).balance(&fallback), 200);
+    assert_eq!(client.status_counts().refunded, 2);
+}
+
+#[test]
+fn test_dry_run_release_mirrors_release_checks() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let lock_id = import_lock_for_release(&env, &client, &token);
+    let user = Address::generate(&env);
+    fund(&env, &token, &admin, 500);
+
+    assert!(client.dry_run_release(&admin, &lock_id, &500, &user, &token));
+    assert!(client.try_dry_run_release(&admin, &lock_id, &501, &user, &token).is_err());
+
+    client.set_recipient_lifetime_cap(&100);
+    assert!(client.try_dry_run_release(&admin, &lock_id, &101, &user, &token).is_err());
+
+    // Nothing moved during the dry runs
+    assert_eq!(token::Client::new(&env, &token).balance(&admin), 500);
+}
+
+#[test]
+fn test_lock_fee_fixed_at_creation() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    let lock_id = lock_default(&env, &client, &user, &token, 100);
+    client.set_token_config(
+        &token,
+        &TokenConfig {
+            fee_scaled: Some(100_000),
+            paused: false,
+            decimals: 7,
+            low_balance_threshold: 0,
+            min_swaped: 1,
+        },
+    );
+    let later_id = lock_default(&env, &client, &user, &token, 100);
+
+    let locks = client.get_locks_by_ids(&vec![&env, lock_id, later_id]);
+    let lock = locks.get(0).unwrap();
+    assert_eq!(lock.fee_scaled_applied, 30_000);
+    assert_eq!(lock.swaped_amount, 97);
+    assert_eq!(locks.get(1).unwrap().fee_scaled_applied, 100_000);
+}
+
+#[test]
+fn test_token_config_defaults_and_round_trip() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+
+    let token_config = client.get_token_config(&token);
+    assert_eq!(token_config.fee_scaled, None);
+    assert!(!token_config.paused);
+    assert_eq!(token_config.decimals, 7);
+    assert_eq!(token_config.low_balance_threshold, 0);
+    assert_eq!(token_config.min_swaped, 1);
+
+    client.set_token_config(
+        &token,
+        &TokenConfig {
+            fee_scaled: Some(500),
+            paused: true,
+            decimals: 18,
+            low_balance_threshold: 50,
+            min_swaped: 10,
+        },
+    );
+    assert!(has_event(&env, "TokenConfigUpdatedEvent"));
+    let token_config = client.get_token_config(&token);
+    assert_eq!(token_config.fee_scaled, Some(500));
+    assert!(token_config.paused);
+    // Decimals always come from the token itself
+    assert_eq!(token_config.decimals, 7);
+    assert_eq!(token_config.low_balance_threshold, 50);
+    assert_eq!(token_config.min_swaped, 10);
+}
+
+#[test]
+fn test_token_fee_override_is_scaled() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 100_000);
+
+    let mut token_config = client.get_token_config(&token);
+    token_config.fee_scaled = Some(FEE_SCALE + 1);
+    assert_eq!(
+        client.try_set_token_config(&token, &token_config),
+        Err(Ok(LockError::InvalidFee.into()))
+    );
+
+    // 2.5 basis points
+    token_config.fee_scaled = Some(250);
+    client.set_token_config(&token, &token_config);
+    let lock_id = lock_default(&env, &client, &user, &token, 100_000);
+    let lock = client.get_locks_by_ids(&vec![&env, lock_id]).get(0).unwrap();
+    assert_eq!(lock.fee_scaled_applied, 250);
+    assert_eq!(lock.swaped_amount, 99_975);
+}
+
+#[test]
+fn test_list_known_tokens() {
+    let env = Env::default();
+    let (client, owner, _admin, token) = setup(&env);
+    let other_token = env.register_stellar_asset_contract_v2(owner).address();
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    fund(&env, &other_token, &user, 1_000);
+
+    assert_eq!(client.list_known_tokens().len(), 0);
+    lock_default(&env, &client, &user, &token, 100);
+    lock_default(&env, &client, &user, &other_token, 100);
+    lock_default(&env, &client, &user, &token, 200);
+
+    assert_eq!(client.list_known_tokens(), vec![&env, token, other_token]);
+}
+
+#[test]
+fn test_max_pending_per_user() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    client.set_max_pending_per_user(&2);
+    let first_id = lock_default(&env, &client, &user, &token, 100);
+    lock_default(&env, &client, &user, &token, 100);
+    assert_eq!(client.get_pending_count(&user), 2);
+    assert!(client
+        .try_lock(
+            &user,
+            &token,
+            &String::from_str(&env, "USDC"),
+            &100,
+            &Bytes::from_slice(&env, b"eth"),
+            &String::from_str(&env, "0xabc"),
+            &false,
+            &None,
+            &None,
+            &0,
+        )
+        .is_err());
+
+    // Settling a lock frees up a slot
+    client.refund(&first_id);
+    assert_eq!(client.get_pending_count(&user), 1);
+    lock_default(&env, &client, &user, &token, 100);
+}
+
+#[test]
+fn test_mark_processing_batch_skips_ineligible() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    let first_id = lock_default(&env, &client, &user, &token, 100);
+    let second_id = lock_default(&env, &client, &user, &token, 200);
+    let refunded_id = lock_default(&env, &client, &user, &token, 300);
+    client.refund(&refunded_id);
+    client.mark_processing(&second_id);
+
+    let transitioned =
+        client.mark_processing_batch(&vec![&env, first_id, second_id, refunded_id, 99]);
+    assert_eq!(transitioned, vec![&env, first_id]);
+    assert_eq!(client.status_counts().processing, 2);
+}
+
+#[test]
+fn test_sub_basis_point_fee() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    let in_amount: i128 = 10_000_000_000_000;
+    fund(&env, &token, &user, in_amount);
+
+    assert_eq!(client.get_fee_scaled(), 30_000);
+
+    // 2.5 bps
+    client.set_fee_scaled(&250);
+    lock_default(&env, &client, &user, &token, in_amount);
+    assert_eq!(
+        token::Client::new(&env, &token).balance(&admin),
+        in_amount - 2_500_000_000
+    );
+
+    assert!(client.try_set_fee_scaled(&1_000_001).is_err());
+    assert!(client.try_set_fee_scaled(&-1).is_err());
+}
+
+#[test]
+fn test_lock_storage_key_reads_lock() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    let lock_id = lock_default(&env, &client, &user, &token, 100);
+    let key = client.lock_storage_key(&lock_id);
+    let lock_data: LockData = env.as_contract(&client.address, || {
+        env.storage().instance().get(&key).unwrap()
+    });
+    assert_eq!(lock_data.in_amount, 100);
+}
+
+#[test]
+fn test_settlement_batches() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    let first_id = lock_default(&env, &client, &user, &token, 100);
+    let second_id = lock_default(&env, &client, &user, &token, 200);
+    let other_chain_id = client.lock(
+        &user,
+        &token,
+        &String::from_str(&env, "USDC"),
+        &100,
+        &Bytes::from_slice(&env, b"sol"),
+        &String::from_str(&env, "0xabc"),
+        &false,
+        &None,
+        &None,
+        &0,
+    );
+
+    assert!(client
+        .try_create_settlement_batch(&vec![&env, first_id, other_chain_id])
+        .is_err());
+
+    let batch_id = client.create_settlement_batch(&vec![&env, first_id, second_id]);
+    assert_eq!(client.get_batch(&batch_id), vec![&env, first_id, second_id]);
+
+    // Already batched locks cannot join another batch
+    assert!(client
+        .try_create_settlement_batch(&vec![&env, second_id])
+        .is_err());
+}
+
+#[test]
+fn test_role_separation_rejects_owner_as_admin() {
+    let env = Env::default();
+    let (client, owner, admin, _token) = setup(&env);
+
+    client.set_require_role_separation(&true);
+    assert!(client.get_require_role_separation());
+
+    client.remove_admin(&owner, &admin);
+    assert!(client.try_add_admin(&owner, &owner).is_err());
+    let other_admin = Address::generate(&env);
+    client.add_admin(&owner, &other_admin);
+
+    // Without the policy the owner may hold both roles, but then it cannot be re-enabled
+    client.set_require_role_separation(&false);
+    client.remove_admin(&owner, &other_admin);
+    client.add_admin(&owner, &owner);
+    assert!(client.try_set_require_role_separation(&true).is_err());
+}
+
+#[test]
+fn test_role_separation_covers_chain_admins() {
+    let env = Env::default();
+    let (client, owner, _admin, _token) = setup(&env);
+    let eth = Bytes::from_slice(&env, b"eth");
+
+    client.set_require_role_separation(&true);
+    assert_eq!(
+        client.try_set_chain_admin(&owner, &eth, &owner),
+        Err(Ok(LockError::RoleSeparationViolated.into()))
+    );
+
+    // An owner already administering a chain blocks turning the policy on
+    client.set_require_role_separation(&false);
+    client.set_chain_admin(&owner, &eth, &owner);
+    assert_eq!(
+        client.try_set_require_role_separation(&true),
+        Err(Ok(LockError::RoleSeparationViolated.into()))
+    );
+    client.remove_chain_admin(&owner, &eth);
+    client.set_require_role_separation(&true);
+}
+
+#[test]
+fn test_cap_warning_emitted_near_cap() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    client.set_max_pending_per_user(&4);
+    client.set_cap_warning_percent(&50);
+
+    lock_default(&env, &client, &user, &token, 100);
+    assert!(!has_event(&env, "CapWarningEvent"));
+    lock_default(&env, &client, &user, &token, 100);
+    assert!(!has_event(&env, "CapWarningEvent"));
+    lock_default(&env, &client, &user, &token, 100);
+    assert!(has_event(&env, "CapWarningEvent"));
+}
+
+#[test]
+fn test_remaining_capacity() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let lock_id = import_lock_for_release(&env, &client, &token);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    fund(&env, &token, &admin, 1_000);
+
+    let capacity = client.remaining_capacity(&token, &user);
+    assert_eq!(capacity.releasable, 1_000);
+    assert_eq!(capacity.pending_slots, None);
+    assert_eq!(capacity.recipient_lifetime, None);
+
+    client.set_max_pending_per_user(&3);
+    client.set_recipient_lifetime_cap(&500);
+    lock_default(&env, &client, &user, &token, 100);
+    client.release(&admin, &lock_id, &200, &user, &token);
+
+    let capacity = client.remaining_capacity(&token, &user);
+    assert_eq!(capacity.pending_slots, Some(2));
+    assert_eq!(capacity.recipient_lifetime, Some(300));
+}
+
+#[test]
+fn test_high_value_lock_requires_approval() {
+    let env = Env::default();
+    let (client, owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 10_000);
+    fund(&env, &token, &client.address, 10_000);
+
+    let signing_key = SigningKey::from_bytes(&[7; 32]);
+    client.set_proof_verifier(&owner, &BytesN::from_array(
+        &env,
+        &signing_key.verifying_key().to_bytes(),
+    ));
+    client.set_high_value_threshold(&1_000);
+
+    let lock_id = client.lock(
+        &user,
+        &token,
+        &String::from_str(&env, "USDC"),
+        &1_000,
+        &Bytes::from_slice(&env, b"eth"),
+        &Address::generate(&env).to_string(),
+        &false,
+        &None,
+        &None,
+        &0,
+    );
+    assert!(has_event(&env, "HighValueLockEvent"));
+    assert_eq!(client.status_counts().pending_approval, 1);
+
+    let relayer = Address::generate(&env);
+    let proof = sign_lock(&env, &signing_key, &client.address, lock_id);
+    assert!(client
+        .try_release_with_inclusion_proof(&lock_id, &proof, &relayer)
+        .is_err());
+
+    client.approve_lock(&lock_id);
+    assert!(client.try_approve_lock(&lock_id).is_err());
+    client.release_with_inclusion_proof(&lock_id, &proof, &relayer);
+    assert_eq!(client.status_counts().released, 1);
+}
+
+#[test]
+fn test_activity_metrics() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let lock_id = import_lock_for_release(&env, &client, &token);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    fund(&env, &token, &admin, 1_000);
+
+    env.ledger().with_mut(|li| li.sequence_number = 10);
+    lock_default(&env, &client, &user, &token, 100);
+    lock_default(&env, &client, &user, &token, 200);
+    env.ledger().with_mut(|li| li.sequence_number = 12);
+    client.release(&admin, &lock_id, &50, &user, &token);
+
+    let activity = client.get_activity();
+    assert_eq!(activity.last_lock_ledger, 10);
+    assert_eq!(activity.last_release_ledger, 12);
+    assert_eq!(activity.locks_today, 2);
+    assert_eq!(activity.releases_today, 1);
+
+    // Daily counters roll over while the last-seen ledgers are kept
+    env.ledger().with_mut(|li| li.timestamp += 86_400);
+    let activity = client.get_activity();
+    assert_eq!(activity.locks_today, 0);
+    assert_eq!(activity.releases_today, 0);
+    assert_eq!(activity.last_lock_ledger, 10);
+}
+
+#[test]
+fn test_blocked_recipient_cannot_receive_releases() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let lock_id = import_lock_for_release(&env, &client, &token);
+    let user = Address::generate(&env);
+    fund(&env, &token, &admin, 1_000);
+
+    client.block_recipient(&user);
+    assert!(client.is_recipient_blocked(&user));
+    assert!(client.try_release(&admin, &lock_id, &100, &user, &token).is_err());
+
+    client.unblock_recipient(&user);
+    assert!(!client.is_recipient_blocked(&user));
+    client.release(&admin, &lock_id, &100, &user, &token);
+}
+
+#[test]
+fn test_change_fee_recipient_sweeps_to_old_recipient() {
+    let env = Env::default();
+    let (client, owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    let first = Address::generate(&env);
+    let second = Address::generate(&env);
+    fund(&env, &token, &user, 3_000);
+
+    // With no recipient set, fees so far are the admin's
+    lock_default(&env, &client, &user, &token, 1_000);
+    client.change_fee_recipient(&owner, &first);
+    assert_eq!(token::Client::new(&env, &token).balance(&admin), 1_000);
+    assert_eq!(token::Client::new(&env, &token).balance(&first), 0);
+    assert_eq!(client.get_accrued_fees(&token), 0);
+
+    lock_default(&env, &client, &user, &token, 1_000);
+    assert_eq!(client.get_accrued_fees(&token), 30);
+
+    // Fees collected so far belong to the outgoing recipient
+    client.change_fee_recipient(&owner, &second);
+    assert!(has_event(&env, "FeesSweptEvent"));
+    assert!(has_event(&env, "FeeRecipientChangedEvent"));
+    assert_eq!(token::Client::new(&env, &token).balance(&first), 30);
+    assert_eq!(client.get_accrued_fees(&token), 0);
+    assert_eq!(client.get_fee_recipient(), Some(second.clone()));
+
+    lock_default(&env, &client, &user, &token, 1_000);
+    assert_eq!(client.get_accrued_fees(&token), 30);
+    assert_eq!(token::Client::new(&env, &token).balance(&second), 0);
+}
+
+#[test]
+fn test_memo_schema_enforced_on_lock() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    client.set_memo_schema(&MemoSchema {
+        min_len: 6,
+        max_len: 12,
+        prefix: Bytes::from_slice(&env, b"INV-"),
+    });
+
+    let lock_with_memo = |memo: Option<Bytes>| {
+        client.try_lock(
+            &user,
+            &token,
+            &String::from_str(&env, "USDC"),
+            &100,
+            &Bytes::from_slice(&env, b"eth"),
+            &String::from_str(&env, "0xabc"),
+            &true,
+            &None,
+            &memo,
+            &0,
+        )
+    };
+    assert!(lock_with_memo(None).is_err());
+    assert!(lock_with_memo(Some(Bytes::from_slice(&env, b"PO-12345"))).is_err());
+    assert!(lock_with_memo(Some(Bytes::from_slice(&env, b"INV-1"))).is_err());
+    assert!(lock_with_memo(Some(Bytes::from_slice(&env, b"INV-1234567890"))).is_err());
+
+    let memo = Bytes::from_slice(&env, b"INV-42");
+    let lock_id = lock_with_memo(Some(memo.clone())).unwrap().unwrap();
+    let locks = client.get_locks_by_ids(&vec![&env, lock_id]);
+    assert_eq!(locks.get(0).unwrap().memo, Some(memo));
+
+    client.clear_memo_schema();
+    assert!(lock_with_memo(None).is_ok());
+}
+
+#[test]
+fn test_lock_audit_collects_history() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    let lock_id = lock_default(&env, &client, &user, &token, 100);
+    let audit = client.get_lock_audit(&lock_id);
+    assert_eq!(audit.status, LockStatus::Pending);
+    assert_eq!(audit.original_recipient, None);
+    assert_eq!(audit.settled_to, None);
+
+    env.ledger().with_mut(|li| li.sequence_number = 20);
+    client.override_recipient(&lock_id, &String::from_str(&env, "0xdef"));
+    client.refund(&lock_id);
+
+    let audit = client.get_lock_audit(&lock_id);
+    assert_eq!(audit.status, LockStatus::Refunded);
+    assert_eq!(audit.lock_data.recipient_address, String::from_str(&env, "0xdef"));
+    assert_eq!(audit.original_recipient, Some(String::from_str(&env, "0xabc")));
+    assert_eq!(audit.override_ledger, Some(20));
+    assert_eq!(audit.settled_ledger, Some(20));
+    assert_eq!(audit.settled_to, Some(user));
+    assert_eq!(audit.settled_amount, 100);
+    assert_eq!(audit.batch_id, None);
+}
+
+#[test]
+fn test_dust_fees_accrue_until_flushed() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 10_000);
+    let token_client = token::Client::new(&env, &token);
+
+    client.set_forward_dust_threshold(&50);
+
+    // 3 and 15 are below the threshold and stay in the contract
+    lock_default(&env, &client, &user, &token, 100);
+    env.ledger().with_mut(|li| li.sequence_number += 100);
+    lock_default(&env, &client, &user, &token, 500);
+    assert_eq!(client.get_accrued_fees(&token), 18);
+    assert_eq!(token_client.balance(&admin), 97 + 485);
+
+    // A 60 fee is forwarded immediately
+    env.ledger().with_mut(|li| li.sequence_number += 100);
+    lock_default(&env, &client, &user, &token, 2_000);
+    assert_eq!(client.get_accrued_fees(&token), 18);
+    assert_eq!(token_client.balance(&admin), 97 + 485 + 2_000);
+
+    assert_eq!(client.flush_to_admin(&token), 18);
+    assert_eq!(token_client.balance(&admin), 97 + 485 + 2_000 + 18);
+    assert_eq!(client.get_accrued_fees(&token), 0);
+    assert!(client.try_flush_to_admin(&token).is_err());
+}
+
+#[test]
+fn test_sunset_ledger_stops_new_locks_only() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let release_lock_id = import_lock_for_release(&env, &client, &token);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    client.set_sunset_ledger(&50);
+    assert_eq!(client.get_sunset_ledger(), 50);
+    let lock_id = lock_default(&env, &client, &user, &token, 100);
+
+    env.ledger().with_mut(|li| li.sequence_number = 50);
+    assert!(client
+        .try_lock(
+            &user,
+            &token,
+            &String::from_str(&env, "USDC"),
+            &100,
+            &Bytes::from_slice(&env, b"eth"),
+            &String::from_str(&env, "0xabc"),
+            &true,
+            &None,
+            &None,
+            &0,
+        )
+        .is_err());
+
+    // Outstanding locks can still be wound down
+    client.refund(&lock_id);
+    fund(&env, &token, &admin, 100);
+    client.release(&admin, &release_lock_id, &100, &user, &token);
+}
+
+#[test]
+fn test_min_swaped_enforced_per_token() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 10_000);
+    assert_eq!(client.get_min_swaped(&token), 1);
+
+    client.set_token_config(
+        &token,
+        &TokenConfig {
+            fee_scaled: None,
+            paused: false,
+            decimals: 7,
+            low_balance_threshold: 0,
+            min_swaped: 1_000,
+        },
+    );
+    assert_eq!(client.get_min_swaped(&token), 1_000);
+
+    // 1_000 in leaves 970 after the 3% fee
+    assert!(client
+        .try_lock(
+            &user,
+            &token,
+            &String::from_str(&env, "USDC"),
+            &1_000,
+            &Bytes::from_slice(&env, b"eth"),
+            &String::from_str(&env, "0xabc"),
+            &false,
+            &None,
+            &None,
+            &0,
+        )
+        .is_err());
+    lock_default(&env, &client, &user, &token, 1_100);
+}
+
+#[test]
+fn test_has_admin_tracks_admin_removal() {
+    let env = Env::default();
+    let (client, owner, admin, _token) = setup(&env);
+    assert!(client.has_admin());
+
+    client.remove_admin(&owner, &admin);
+    assert!(!client.has_admin());
+
+    client.add_admin(&owner, &admin);
+    assert!(client.has_admin());
+}
+
+#[test]
+fn test_release_into_registered_receiver_contract() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let lock_id = import_lock_for_release(&env, &client, &token);
+    fund(&env, &token, &admin, 1_000);
+
+    let receiver_id = env.register_contract(None, ReleaseRecorder);
+    let receiver = ReleaseRecorderClient::new(&env, &receiver_id);
+
+    // Unregistered contracts are paid like any other address
+    client.release(&admin, &lock_id, &100, &receiver_id, &token);
+    assert_eq!(receiver.received(), None);
+
+    client.register_receiver(&receiver_id);
+    assert!(client.is_registered_receiver(&receiver_id));
+    client.release(&admin, &lock_id, &200, &receiver_id, &token);
+    assert_eq!(receiver.received(), Some(200));
+
+    // A rejecting receiver reverts the whole release
+    receiver.set_reject(&true);
+    assert!(client.try_release(&admin, &lock_id, &300, &receiver_id, &token).is_err());
+    assert_eq!(token::Client::new(&env, &token).balance(&receiver_id), 300);
+}
+
+#[test]
+fn test_average_fee_bps_over_lifetime() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 10_000);
+    assert_eq!(client.average_fee_bps(), 0);
+
+    lock_default(&env, &client, &user, &token, 1_000);
+    assert_eq!(client.average_fee_bps(), 300);
+
+    // A fee-free lock of the same size halves the average
+    client.set_fee_scaled(&0);
+    env.ledger().with_mut(|li| li.sequence_number += 100);
+    lock_default(&env, &client, &user, &token, 1_000);
+    let stats = client.get_fee_stats();
+    assert_eq!(stats.total_in, 2_000);
+    assert_eq!(stats.total_fees, 30);
+    assert_eq!(client.average_fee_bps(), 150);
+}
+
+#[test]
+fn test_locks_by_priority() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 10_000);
+
+    let lock_with_priority = |priority: u32| {
+        client.try_lock(
+            &user,
+            &token,
+            &String::from_str(&env, "USDC"),
+            &100,
+            &Bytes::from_slice(&env, b"eth"),
+            &String::from_str(&env, "0xabc"),
+            &true,
+            &None,
+            &None,
+            &priority,
+        )
+    };
+    assert!(lock_with_priority(11).is_err());
+
+    let normal = lock_with_priority(0).unwrap().unwrap();
+    let urgent = lock_with_priority(5).unwrap().unwrap();
+    let critical = lock_with_priority(10).unwrap().unwrap();
+    assert_eq!(client.get_locks_by_ids(&vec![&env, critical]).get(0).unwrap().priority, 10);
+
+    assert_eq!(client.get_locks_by_priority(&5, &0, &10), vec![&env, urgent, critical]);
+    assert_eq!(client.get_locks_by_priority(&0, &0, &10), vec![&env, normal, urgent, critical]);
+
+    // Settled locks drop out of the relayer queue
+    client.refund(&urgent);
+    assert_eq!(client.get_locks_by_priority(&5, &0, &10), vec![&env, critical]);
+}
+
+#[test]
+fn test_add_allowed_tokens_in_bulk() {
+    let env = Env::default();
+    let (client, owner, _admin, token) = setup(&env);
+    let other = env.register_stellar_asset_contract_v2(owner.clone()).address();
+    let unlisted = env.register_stellar_asset_contract_v2(owner).address();
+    let user = Address::generate(&env);
+    fund(&env, &unlisted, &user, 1_000);
+    fund(&env, &token, &user, 1_000);
+
+    // Duplicates are skipped rather than rejected
+    assert_eq!(client.add_allowed_tokens(&vec![&env, token.clone(), other.clone(), token.clone()]), 2);
+    assert!(has_event(&env, "AllowedTokensAddedEvent"));
+    assert_eq!(client.get_allowed_tokens(), vec![&env, token.clone(), other.clone()]);
+    assert_eq!(client.add_allowed_tokens(&vec![&env, other.clone()]), 0);
+
+    // Addresses that are not token contracts fail the whole batch
+    assert!(client
+        .try_add_allowed_tokens(&vec![&env, Address::generate(&env)])
+        .is_err());
+
+    lock_default(&env, &client, &user, &token, 100);
+    assert!(client
+        .try_lock(
+            &user,
+            &unlisted,
+            &String::from_str(&env, "USDC"),
+            &100,
+            &Bytes::from_slice(&env, b"eth"),
+            &String::from_str(&env, "0xabc"),
+            &true,
+            &None,
+            &None,
+            &0,
+        )
+        .is_err());
+}
+
+#[test]
+fn test_unknown_dest_token_rejected_when_validating() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    // Free-form destination tokens are accepted until validation is enabled
+    lock_default(&env, &client, &user, &token, 100);
+
+    client.set_validate_dest_token(&true);
+    assert!(client.get_validate_dest_token());
+    let lock_to = |dest_token: &str| {
+        client.try_lock(
+            &user,
+            &token,
+            &String::from_str(&env, dest_token),
+            &100,
+            &Bytes::from_slice(&env, b"eth"),
+            &String::from_str(&env, "0xabc"),
+            &true,
+            &None,
+            &None,
+            &0,
+        )
+    };
+    assert!(lock_to("USDC").is_err());
+
+    client.add_dest_token(&String::from_str(&env, "USDC"));
+    assert!(client.is_known_dest_token(&String::from_str(&env, "USDC")));
+    assert!(lock_to("USDC").is_ok());
+    assert!(lock_to("UDSC").is_err());
+}
+
+#[test]
+fn test_get_limits_reports_active_bounds() {
+    let env = Env::default();
+    let (client, _owner, _admin, _token) = setup(&env);
+
+    let limits = client.get_limits();
+    assert_eq!(limits.max_pending_per_user, None);
+    assert_eq!(limits.sunset_ledger, None);
+    assert_eq!(limits.max_priority, 10);
+
+    client.set_max_pending_per_user(&3);
+    client.set_sunset_ledger(&1_000);
+    client.set_dup_window(&5);
+    let limits = client.get_limits();
+    assert_eq!(limits.max_pending_per_user, Some(3));
+    assert_eq!(limits.sunset_ledger, Some(1_000));
+    assert_eq!(limits.dup_window_ledgers, Some(5));
+    assert_eq!(limits.recipient_lifetime_cap, None);
+}
+
+#[test]
+fn test_reverse_release_within_dispute_window() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let lock_id = import_lock_for_release(&env, &client, &token);
+    let user = Address::generate(&env);
+    fund(&env, &token, &admin, 1_000);
+    let token_client = token::Client::new(&env, &token);
+
+    client.release(&admin, &lock_id, &300, &user, &token);
+    // Reversals are off until a window is configured
+    assert!(client.try_reverse_release(&0).is_err());
+
+    client.set_dispute_window(&10);
+    token_client.approve(&user, &client.address, &1_000, &200);
+    client.reverse_release(&0);
+    assert!(has_event(&env, "ReleaseReversedEvent"));
+    assert!(client.get_release(&0).reversed);
+    assert_eq!(token_client.balance(&admin), 1_000);
+    assert_eq!(token_client.balance(&user), 0);
+    assert_eq!(client.get_recipient_released(&user), 0);
+    assert!(client.try_reverse_release(&0).is_err());
+
+    // A fully released lock goes back to pending and can be released again
+    fund(&env, &token, &admin, 9_000);
+    client.set_release_cap(&token, &15_000);
+    client.release(&admin, &lock_id, &10_000, &user, &token);
+    assert_eq!(client.get_release(&1).lock_id, lock_id);
+    assert_eq!(client.get_lock_audit(&lock_id).status, LockStatus::Released);
+    token_client.approve(&user, &client.address, &10_000, &200);
+    client.reverse_release(&1);
+    let audit = client.get_lock_audit(&lock_id);
+    assert_eq!(audit.status, LockStatus::Pending);
+    assert_eq!(audit.released_amount, 0);
+    assert_eq!(client.max_releasable(&token), 10_000);
+
+    // Outside the window the release stands
+    client.release(&admin, &lock_id, &300, &user, &token);
+    env.ledger().with_mut(|li| li.sequence_number += 10);
+    assert!(client.try_reverse_release(&2).is_err());
+    assert_eq!(token_client.balance(&user), 300);
+}
+
+#[test]
+fn test_token_custodian_holds_retained_funds() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    let custodian = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(client.get_token_custodian(&token), client.address);
+
+    client.set_token_custodian(&token, &custodian);
+    assert_eq!(client.get_token_custodian(&token), custodian);
+
+    let refunded = lock_default(&env, &client, &user, &token, 100);
+    env.ledger().with_mut(|li| li.sequence_number += 100);
+    lock_default(&env, &client, &user, &token, 500);
+    assert_eq!(token_client.balance(&custodian), 18);
+    assert_eq!(token_client.balance(&client.address), 0);
+
+    // Refunds and fee payouts are drawn from the custodian
+    client.refund(&refunded);
+    assert_eq!(token_client.balance(&custodian), 15);
+    assert_eq!(client.flush_to_admin(&token), 15);
+    assert_eq!(token_client.balance(&custodian), 0);
+}
+
+#[test]
+fn test_cost_breakdown_matches_lock() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    let dest_chain = Bytes::from_slice(&env, b"eth");
+
+    let breakdown = client.cost_breakdown(&token, &1_000, &dest_chain);
+    assert_eq!(breakdown.base_fee, 30);
+    assert_eq!(breakdown.surcharge, 0);
+    assert_eq!(breakdown.discount, 0);
+    assert_eq!(breakdown.net_out, 970);
+
+    // A cheaper token override is reported as a discount on the global fee
+    client.set_token_config(
+        &token,
+        &TokenConfig {
+            fee_scaled: Some(10_000),
+            paused: false,
+            decimals: 7,
+            low_balance_threshold: 0,
+            min_swaped: 1,
+        },
+    );
+    let breakdown = client.cost_breakdown(&token, &1_000, &dest_chain);
+    assert_eq!(breakdown.discount, 20);
+    assert_eq!(breakdown.fee, 10);
+
+    lock_default(&env, &client, &user, &token, 1_000);
+    assert_eq!(token::Client::new(&env, &token).balance(&admin), breakdown.net_out);
+}
+
+#[test]
+fn test_emergency_drain_only_while_paused() {
+    let env = Env::default();
+    let (client, owner, _admin, token) = setup(&env);
+    let other = env.register_stellar_asset_contract_v2(owner).address();
+    let recovery = Address::generate(&env);
+    fund(&env, &token, &client.address, 500);
+    fund(&env, &other, &client.address, 200);
+    let tokens = vec![&env, token.clone(), other.clone()];
+
+    assert!(client.try_emergency_drain(&tokens, &recovery).is_err());
+
+    client.pause();
+    client.emergency_drain(&tokens, &recovery);
+    assert!(has_event(&env, "EmergencyDrainEvent"));
+    assert_eq!(token::Client::new(&env, &token).balance(&recovery), 500);
+    assert_eq!(token::Client::new(&env, &other).balance(&recovery), 200);
+    assert_eq!(token::Client::new(&env, &token).balance(&client.address), 0);
+}
+
+#[test]
+fn test_emergency_drain_settles_protocol_balances() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    let recovery = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    let token_client = token::Client::new(&env, &token);
+
+    // Four rounded-down fees carry three whole units into custody
+    let mut lock_ids = Vec::new(&env);
+    for _ in 0..4 {
+        env.ledger().with_mut(|li| li.sequence_number += 100);
+        lock_ids.push_back(lock_default(&env, &client, &user, &token, 33));
+    }
+    client.set_hold_principal(&true);
+    let held = lock_default(&env, &client, &user, &token, 200);
+    assert_eq!(client.get_accrued_fees(&token), 6);
+    assert_eq!(client.get_protocol_remainder(&token), 3_960_000);
+
+    client.pause();
+    client.emergency_drain(&vec![&env, token.clone()], &recovery);
+    assert_eq!(token_client.balance(&recovery), 3 + 200);
+    assert_eq!(client.get_accrued_fees(&token), 0);
+    assert_eq!(client.get_protocol_remainder(&token), 960_000);
+    client.unpause();
+
+    // The held principal stays reserved for its lock once custody is refunded
+    fund(&env, &token, &client.address, 200);
+    assert_eq!(
+        client.try_release_partial(&lock_ids.get(0).unwrap(), &33),
+        Err(Ok(LockError::InsufficientLiquidity.into()))
+    );
+    client.refund(&held);
+    assert_eq!(token_client.balance(&user), 1_000 - 4 * 33);
+}
+
+#[test]
+fn test_admin_for_chain_falls_back_to_global() {
+    let env = Env::default();
+    let (client, owner, admin, _token) = setup(&env);
+    let eth = Bytes::from_slice(&env, b"eth");
+    let sol = Bytes::from_slice(&env, b"sol");
+    let eth_admin = Address::generate(&env);
+
+    client.set_chain_admin(&owner, &eth, &eth_admin);
+    assert_eq!(client.admin_for_chain(&eth), eth_admin);
+    assert_eq!(client.admin_for_chain(&sol), admin);
+
+    client.remove_chain_admin(&owner, &eth);
+    assert_eq!(client.admin_for_chain(&eth), admin);
+}
+
+#[test]
+fn test_chain_admin_releases_its_chain() {
+    let env = Env::default();
+    let (client, owner, admin, token) = setup(&env);
+    let lock_id = import_lock_for_release(&env, &client, &token);
+    let user = Address::generate(&env);
+    let eth_admin = Address::generate(&env);
+    fund(&env, &token, &admin, 1_000);
+    fund(&env, &token, &eth_admin, 1_000);
+
+    client.set_chain_admin(&owner, &Bytes::from_slice(&env, b"eth"), &eth_admin);
+    assert_eq!(
+        client.try_release(&admin, &lock_id, &100, &user, &token),
+        Err(Ok(LockError::NotAdmin.into()))
+    );
+
+    client.release(&eth_admin, &lock_id, &100, &user, &token);
+    assert_eq!(env.auths()[0].0, eth_admin);
+    assert_eq!(token::Client::new(&env, &token).balance(&user), 100);
+}
+
+#[test]
+fn test_locks_stored_independently_by_id() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    fund(&env, &token, &alice, 1_000);
+    fund(&env, &token, &bob, 1_000);
+
+    let first = lock_default(&env, &client, &alice, &token, 100);
+    let second = lock_default(&env, &client, &bob, &token, 200);
+    env.ledger().with_mut(|li| li.sequence_number += 100);
+    let third = lock_default(&env, &client, &alice, &token, 300);
+
+    // The lock id is the first topic after the event name
+    let (_, topics, _) = env.events().all().last().unwrap();
+    assert_eq!(u64::try_from_val(&env, &topics.get(1).unwrap()), Ok(third));
+
+    assert_eq!((first, second, third), (0, 1, 2));
+    let locks = client.get_locks_by_ids(&vec![&env, first, second, third]);
+    assert_eq!(locks.get(0).unwrap().user_address, alice);
+    assert_eq!(locks.get(0).unwrap().in_amount, 100);
+    assert_eq!(locks.get(1).unwrap().user_address, bob);
+    assert_eq!(locks.get(1).unwrap().in_amount, 200);
+    assert_eq!(locks.get(2).unwrap().user_address, alice);
+    assert_eq!(locks.get(2).unwrap().in_amount, 300);
+}
+
+#[test]
+fn test_failures_report_distinct_lock_errors() {
+    let env = Env::default();
+    let (client, owner, admin, token) = setup(&env);
+    let lock_id = import_lock_for_release(&env, &client, &token);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    assert_eq!(
+        client.try_initialize(&owner, &3, &0, &1, &0),
+        Err(Ok(LockError::AlreadyInitialized.into()))
+    );
+    assert_eq!(
+        client.try_refund(&7),
+        Err(Ok(LockError::LockNotFound.into()))
+    );
+
+    client.remove_admin(&owner, &admin);
+    assert_eq!(
+        client.try_release(&admin, &lock_id, &1, &user, &token),
+        Err(Ok(LockError::AdminNotSet.into()))
+    );
+    let admin_not_set = Err(Ok(LockError::AdminNotSet.into()));
+    let lock_ids = vec![&env, lock_id];
+    assert_eq!(client.try_refund(&lock_id).map(|_| ()), admin_not_set);
+    assert_eq!(client.try_refund_batch(&lock_ids).map(|_| ()), admin_not_set);
+    assert_eq!(client.try_cancel_lock(&lock_id).map(|_| ()), admin_not_set);
+    assert_eq!(client.try_approve_lock(&lock_id).map(|_| ()), admin_not_set);
+    assert_eq!(client.try_mark_processing(&lock_id).map(|_| ()), admin_not_set);
+    assert_eq!(client.try_mark_processing_batch(&lock_ids).map(|_| ()), admin_not_set);
+    assert_eq!(client.try_create_settlement_batch(&lock_ids).map(|_| ()), admin_not_set);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #4)")]
+fn test_zero_amount_lock_panics_with_invalid_amount() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    lock_default(&env, &client, &user, &token, 0);
+}
+
+#[test]
+fn test_rounding_remainders_are_conserved() {
+    let env = Env::default();
+    let (client, owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    let token_client = token::Client::new(&env, &token);
+
+    // 3% of 33 is 0.99, which rounds the fee down to zero on every lock
+    let mut lock_ids = Vec::new(&env);
+    for _ in 0..4 {
+        env.ledger().with_mut(|li| li.sequence_number += 100);
+        lock_ids.push_back(lock_default(&env, &client, &user, &token, 33));
+    }
+    assert_eq!(client.get_accrued_fees(&token), 0);
+    assert_eq!(client.get_protocol_remainder(&token), 3_960_000);
+    assert_eq!(token_client.balance(&admin), 33 + 32 * 3);
+    assert_eq!(token_client.balance(&client.address), 3);
+    assert_eq!(
+        token_client.balance(&user) + token_client.balance(&admin) + token_client.balance(&client.address),
+        1_000
+    );
+
+    // Refunding a lock hands back the unit it carried
+    client.refund(&lock_ids.get(1).unwrap());
+    assert_eq!(client.get_protocol_remainder(&token), 2_960_000);
+    assert_eq!(token_client.balance(&client.address), 2);
+
+    assert_eq!(client.claim_remainder(&token), 2);
+    assert_eq!(client.get_protocol_remainder(&token), 960_000);
+    assert_eq!(token_client.balance(&owner), 2);
+    assert_eq!(token_client.balance(&client.address), 0);
+    assert_eq!(
+        token_client.balance(&user) + token_client.balance(&admin) + token_client.balance(&owner),
+        1_000
+    );
+    assert!(client.try_claim_remainder(&token).is_err());
+}
+
+#[test]
+fn test_simulate_round_trip_matches_lock() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    let simulated = client.simulate_round_trip(
+        &user,
+        &token,
+        &1_000,
+        &String::from_str(&env, "USDC"),
+        &Bytes::from_slice(&env, b"eth"),
+        &String::from_str(&env, "0xabc"),
+    );
+    assert_eq!(simulated.release_amount, 970);
+    assert!(!simulated.requires_approval);
+    assert_eq!(client.status_counts().pending, 0);
+
+    let lock_id = lock_default(&env, &client, &user, &token, 1_000);
+    assert_eq!(lock_id, simulated.lock_id);
+    let stored = client.get_locks_by_ids(&vec![&env, lock_id]).get(0).unwrap();
+    assert_eq!(stored.swaped_amount, simulated.lock_data.swaped_amount);
+    assert_eq!(stored.fee_scaled_applied, simulated.lock_data.fee_scaled_applied);
+    assert_eq!(stored.created_ledger, simulated.lock_data.created_ledger);
+}
+
+#[test]
+fn test_config_admin_and_owner_getters() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, LockAndReleaseContract);
+    let client = LockAndReleaseContractClient::new(&env, &contract_id);
+    let owner = Address::generate(&env);
+    let admin = Address::generate(&env);
+
+    client.initialize(&owner, &5, &0, &1, &0);
+    assert_eq!(client.get_owner(), owner);
+    assert_eq!(client.get_admin(), None);
+    assert_eq!(client.get_config().fee_scaled, 50_000);
+
+    client.add_admin(&owner, &admin);
+    assert_eq!(client.get_admin(), Some(admin));
+}
+
+#[test]
+fn test_set_paused_halts_locks_and_releases() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let lock_id = import_lock_for_release(&env, &client, &token);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    fund(&env, &token, &admin, 1_000);
+
+    client.set_paused(&true);
+    assert!(client.is_paused());
+    assert!(has_event(&env, "ContractPausedEvent"));
+    assert_eq!(
+        client.try_release(&admin, &lock_id, &100, &user, &token),
+        Err(Ok(LockError::ContractPaused.into()))
+    );
+    assert_eq!(
+        client.try_lock(
+            &user,
+            &token,
+            &String::from_str(&env, "USDC"),
+            &100,
+            &Bytes::from_slice(&env, b"eth"),
+            &String::from_str(&env, "0xabc"),
+            &false,
+            &None,
+            &None,
+            &0,
+        ),
+        Err(Ok(LockError::ContractPaused.into()))
+    );
+    assert_eq!(client.get_config().fee_scaled, 30_000);
+
+    client.set_paused(&false);
+    assert!(!client.is_paused());
+    lock_default(&env, &client, &user, &token, 100);
+    client.release(&admin, &lock_id, &100, &user, &token);
+}
+
+#[test]
+fn test_token_pause_records_reason() {
+    let env = Env::default();
+    let (client, owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    assert_eq!(client.token_pause_info(&token), None);
+
+    client.pause_token(&token, &symbol_short!("depeg"));
+    assert!(has_event(&env, "TokenPausedEvent"));
+    assert_eq!(client.token_pause_info(&token), Some((symbol_short!("depeg"), owner)));
+    assert!(client.get_token_config(&token).paused);
+    assert!(client
+        .try_lock(
+            &user,
+            &token,
+            &String::from_str(&env, "USDC"),
+            &100,
+            &Bytes::from_slice(&env, b"eth"),
+            &String::from_str(&env, "0xabc"),
+            &false,
+            &None,
+            &None,
+            &0,
+        )
+        .is_err());
+
+    client.unpause_token(&token);
+    assert_eq!(client.token_pause_info(&token), None);
+    lock_default(&env, &client, &user, &token, 100);
+}
+
+#[test]
+fn test_is_valid_for_chain_tracks_current_rules() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    let lock_id = lock_default(&env, &client, &user, &token, 100);
+    assert!(client.is_valid_for_chain(&lock_id));
+
+    // Turning on destination token validation invalidates unregistered tokens
+    client.set_validate_dest_token(&true);
+    assert!(!client.is_valid_for_chain(&lock_id));
+    client.add_dest_token(&String::from_str(&env, "USDC"));
+    assert!(client.is_valid_for_chain(&lock_id));
+
+    // Once chains are registered, a lock to an unregistered chain is invalid
+    client.register_chain(&Bytes::from_slice(&env, b"sol"));
+    assert!(!client.is_valid_for_chain(&lock_id));
+    client.register_chain(&Bytes::from_slice(&env, b"eth"));
+    assert!(client.is_valid_for_chain(&lock_id));
+
+    client.refund(&lock_id);
+    assert!(!client.is_valid_for_chain(&lock_id));
+}
+
+#[test]
+fn test_initialize_validates_fee_percentage() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let owner = Address::generate(&env);
+
+    for (fee_percentage, valid) in [(0, true), (100, true), (-1, false), (101, false)] {
+        let contract_id = env.register_contract(None, LockAndReleaseContract);
+        let client = LockAndReleaseContractClient::new(&env, &contract_id);
+        let result = client.try_initialize(&owner, &fee_percentage, &0, &1, &0);
+        if valid {
+            assert!(result.is_ok());
+        } else {
+            assert_eq!(result, Err(Ok(LockError::InvalidFee.into())));
+        }
+    }
+}
+
+#[test]
+fn test_partial_releases_complete_to_released() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    fund(&env, &token, &client.address, 1_000);
+
+    let lock_id = client.lock(
+        &user,
+        &token,
+        &String::from_str(&env, "USDC"),
+        &100,
+        &Bytes::from_slice(&env, b"eth"),
+        &recipient.to_string(),
+        &false,
+        &None,
+        &None,
+        &0,
+    );
+
+    client.release_partial(&lock_id, &40);
+    assert_eq!(client.get_lock_audit(&lock_id).status, LockStatus::Pending);
+    assert!(!has_event(&env, "LockStatusChangedEvent"));
+
+    // More than what is still owed is rejected
+    assert_eq!(
+        client.try_release_partial(&lock_id, &58),
+        Err(Ok(LockError::InvalidAmount.into()))
+    );
+
+    client.release_partial(&lock_id, &57);
+    let audit = client.get_lock_audit(&lock_id);
+    assert_eq!(audit.status, LockStatus::Released);
+    assert_eq!(audit.released_amount, 97);
+    assert_eq!(audit.settled_amount, 97);
+    assert!(has_event(&env, "LockStatusChangedEvent"));
+    assert_eq!(token::Client::new(&env, &token).balance(&recipient), 97);
+
+    assert_eq!(
+        client.try_release_partial(&lock_id, &1),
+        Err(Ok(LockError::AlreadyReleased.into()))
+    );
+}
+
+#[test]
+fn test_update_fee_applies_to_later_locks() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    let first = lock_default(&env, &client, &user, &token, 100);
+    client.update_fee(&5);
+    let second = lock_default(&env, &client, &user, &token, 100);
+
+    assert_eq!(client.get_lock_audit(&first).lock_data.swaped_amount, 97);
+    assert_eq!(client.get_lock_audit(&second).lock_data.swaped_amount, 95);
+    assert!(has_event(&env, "FeeUpdatedEvent"));
+
+    assert_eq!(
+        client.try_update_fee(&101),
+        Err(Ok(LockError::InvalidFee.into()))
+    );
+}
+
+#[test]
+fn test_metrics_reports_labeled_counters() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    lock_default(&env, &client, &user, &token, 100);
+    lock_default(&env, &client, &user, &token, 200);
+
+    let metrics = client.metrics();
+    let value = |name: &str| {
+        metrics
+            .iter()
+            .find(|(label, _)| *label == Symbol::new(&env, name))
+            .map(|(_, value)| value)
+            .unwrap()
+    };
+    assert_eq!(value("total_locks"), 2);
+    assert_eq!(value("pending"), 2);
+    assert_eq!(value("total_released"), 0);
+    // The 3% fee stays in contract custody
+    assert_eq!(value("tvl"), 9);
+    assert_eq!(value("accrued_fees"), 9);
+}
+
+#[test]
+fn test_lock_overflowing_fee_is_contract_error() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, i128::MAX);
+
+    let result = client.try_lock(
+        &user,
+        &token,
+        &String::from_str(&env, "USDC"),
+        &i128::MAX,
+        &Bytes::from_slice(&env, b"eth"),
+        &String::from_str(&env, "0xabc"),
+        &false,
+        &None,
+        &None,
+        &0,
+    );
+    assert_eq!(result, Err(Ok(LockError::AmountOverflow.into())));
+}
+
+#[test]
+fn test_batches_over_max_batch_size_rejected() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    let first = lock_default(&env, &client, &user, &token, 100);
+    let second = lock_default(&env, &client, &user, &token, 200);
+    let third = lock_default(&env, &client, &user, &token, 300);
+
+    assert_eq!(
+        client.try_set_max_batch_size(&0),
+        Err(Ok(LockError::InvalidConfig.into()))
+    );
+    client.set_max_batch_size(&2);
+    assert_eq!(client.get_max_batch_size(), 2);
+    assert_eq!(client.get_limits().max_batch_size, 2);
+
+    assert_eq!(
+        client.try_mark_processing_batch(&vec![&env, first, second, third]),
+        Err(Ok(LockError::BatchTooLarge.into()))
+    );
+    assert_eq!(
+        client.try_create_settlement_batch(&vec![&env, first, second, third]),
+        Err(Ok(LockError::BatchTooLarge.into()))
+    );
+
+    // Exactly at the limit is accepted
+    client.create_settlement_batch(&vec![&env, first, second]);
+    assert_eq!(client.mark_processing_batch(&vec![&env, first, second]).len(), 2);
+}
+
+#[test]
+fn test_multiple_admins_release_independently() {
+    let env = Env::default();
+    let (client, owner, admin, token) = setup(&env);
+    let lock_id = import_lock_for_release(&env, &client, &token);
+    let second_admin = Address::generate(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &admin, 1_000);
+    fund(&env, &token, &second_admin, 1_000);
+
+    client.add_admin(&owner, &second_admin);
+    assert_eq!(client.get_admins(), vec![&env, admin.clone(), second_admin.clone()]);
+    assert_eq!(
+        client.try_add_admin(&owner, &second_admin),
+        Err(Ok(LockError::AdminAlreadySet.into()))
+    );
+
+    // Each admin pays from its own balance
+    client.release(&admin, &lock_id, &100, &user, &token);
+    client.release(&second_admin, &lock_id, &200, &user, &token);
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&admin), 900);
+    assert_eq!(token_client.balance(&second_admin), 800);
+    assert_eq!(token_client.balance(&user), 300);
+
+    // Removing the primary admin promotes the next one
+    client.remove_admin(&owner, &admin);
+    assert_eq!(client.get_admin(), Some(second_admin.clone()));
+    assert_eq!(
+        client.try_release(&admin, &lock_id, &100, &user, &token),
+        Err(Ok(LockError::NotAdmin.into()))
+    );
+    client.release(&second_admin, &lock_id, &100, &user, &token);
+}
+
+#[test]
+fn test_upgrade_requires_uploaded_wasm() {
+    let env = Env::default();
+    let (client, _owner, _admin, _token) = setup(&env);
+
+    assert_eq!(client.code_hash(), None);
+
+    // Nothing has been uploaded under this hash, so the upgrade is rolled back
+    let unknown_hash = BytesN::from_array(&env, &[1; 32]);
+    assert!(client.try_upgrade(&unknown_hash).is_err());
+    assert_eq!(client.code_hash(), None);
+}
+
+// The smallest module the host accepts as a contract: only the environment
+// metadata section, declaring interface version 21.0
+const UPGRADE_WASM: [u8; 40] = [
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x00, 0x1e, 0x11, b'c', b'o', b'n', b't',
+    b'r', b'a', b'c', b't', b'e', b'n', b'v', b'm', b'e', b't', b'a', b'v', b'0', 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x15, 0x00, 0x00, 0x00, 0x00,
+];
+
+#[test]
+fn test_upgrade_installs_uploaded_wasm() {
+    let env = Env::default();
+    let (client, owner, _admin, _token) = setup(&env);
+
+    let new_wasm_hash = env
+        .deployer()
+        .upload_contract_wasm(Bytes::from_slice(&env, &UPGRADE_WASM));
+    client.upgrade(&new_wasm_hash);
+    assert_eq!(env.auths()[0].0, owner);
+    assert!(has_event(&env, "UpgradeEvent"));
+
+    // The new code no longer serves this contract's functions, so read the
+    // kept storage directly
+    env.as_contract(&client.address, || {
+        let stored_owner: Address = env.storage().instance().get(&DataKey::Owner).unwrap();
+        assert_eq!(stored_owner, owner);
+        let code_hash: Option<BytesN<32>> = env.storage().instance().get(&DataKey::CodeHash);
+        assert_eq!(code_hash, Some(new_wasm_hash));
+    });
+}
+
+#[test]
+fn test_release_settles_lock_and_rejects_replay() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    fund(&env, &token, &admin, 1_000);
+
+    let lock_id = lock_default(&env, &client, &user, &token, 100);
+
+    // More than the lock's swaped_amount is rejected
+    assert_eq!(
+        client.try_release(&admin, &lock_id, &98, &recipient, &token),
+        Err(Ok(LockError::InvalidAmount.into()))
+    );
+
+    client.release(&admin, &lock_id, &97, &recipient, &token);
+    let audit = client.get_lock_audit(&lock_id);
+    assert_eq!(audit.status, LockStatus::Released);
+    assert_eq!(audit.settled_to, Some(recipient.clone()));
+
+    assert_eq!(
+        client.try_release(&admin, &lock_id, &97, &recipient, &token),
+        Err(Ok(LockError::AlreadyReleased.into()))
+    );
+    assert_eq!(token::Client::new(&env, &token).balance(&recipient), 97);
+}
+
+#[test]
+fn test_cancel_lock_charges_refund_fee() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 2_000);
+
+    assert_eq!(
+        client.try_set_refund_fee_bps(&501),
+        Err(Ok(LockError::InvalidFee.into()))
+    );
+    client.set_refund_fee_bps(&100);
+
+    // An admin refund is free of the cancellation fee
+    let refunded = lock_default(&env, &client, &user, &token, 1_000);
+    client.refund(&refunded);
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&user), 2_000);
+
+    // The user's own cancellation keeps 1% back as a fee
+    let cancelled = lock_default(&env, &client, &user, &token, 1_000);
+    client.cancel_lock(&cancelled);
+    assert_eq!(token_client.balance(&user), 1_990);
+    assert_eq!(client.get_accrued_fees(&token), 10);
+    assert_eq!(client.get_lock_audit(&cancelled).settled_amount, 990);
+    assert!(has_event(&env, "RefundFeeEvent"));
+
+    // A fee on an amount too large to scale is rejected rather than wrapping
+    let huge = LockData {
+        user_address: user.clone(),
+        dest_token: String::from_str(&env, "USDC"),
+        from_token: token.clone(),
+        in_amount: i128::MAX / 50,
+        swaped_amount: 0,
+        recipient_address: String::from_str(&env, "0xabc"),
+        dest_chain: Bytes::from_slice(&env, b"eth"),
+        created_ledger: 0,
+        refund_fallback: None,
+        fee_scaled_applied: 0,
+        memo: None,
+        priority: 0,
+        remainder_charged: 0,
+        fee_payee: None,
+    };
+    client.import_lock(&500, &huge, &LockStatus::Pending);
+    assert_eq!(
+        client.try_cancel_lock(&500),
+        Err(Ok(LockError::AmountOverflow.into()))
+    );
+}
+
+#[test]
+fn test_two_step_ownership_transfer() {
+    let env = Env::default();
+    let (client, owner, admin, _token) = setup(&env);
+    let stale_owner = Address::generate(&env);
+    let new_owner = Address::generate(&env);
+
+    assert_eq!(
+        client.try_accept_ownership(),
+        Err(Ok(LockError::NoPendingOwner.into()))
+    );
+
+    // A second transfer replaces the stale pending entry
+    client.transfer_ownership(&stale_owner);
+    client.transfer_ownership(&new_owner);
+    assert_eq!(client.get_pending_owner(), Some(new_owner.clone()));
+    assert_eq!(client.get_pending_actions().len(), 1);
+
+    // Until acceptance owner-only calls still need the current owner
+    client.set_dup_window(&5);
+    assert_eq!(env.auths()[0].0, owner);
+
+    client.accept_ownership();
+    assert_eq!(env.auths()[0].0, new_owner);
+    assert_eq!(client.get_owner(), new_owner);
+    assert_eq!(client.get_pending_owner(), None);
+    assert!(client.get_pending_actions().is_empty());
+
+    client.set_dup_window(&6);
+    assert_eq!(env.auths()[0].0, new_owner);
+
+    // Role separation keeps an admin from being proposed as owner
+    client.set_require_role_separation(&true);
+    assert_eq!(
+        client.try_transfer_ownership(&admin),
+        Err(Ok(LockError::RoleSeparationViolated.into()))
+    );
+    assert_eq!(client.get_pending_owner(), None);
+
+    // or from accepting a proposal made before it became an admin
+    let later_admin = Address::generate(&env);
+    client.transfer_ownership(&later_admin);
+    client.add_admin(&new_owner, &later_admin);
+    assert_eq!(
+        client.try_accept_ownership(),
+        Err(Ok(LockError::RoleSeparationViolated.into()))
+    );
+}
+
+#[test]
+fn test_user_stats_track_locks_and_releases() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let lock_id = import_lock_for_release(&env, &client, &token);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    fund(&env, &token, &admin, 1_000);
+
+    lock_default(&env, &client, &user, &token, 100);
+    lock_default(&env, &client, &user, &token, 200);
+    client.release(&admin, &lock_id, &50, &user, &token);
+
+    assert_eq!(
+        client.get_user_stats(&user),
+        UserStats {
+            total_locked: 300,
+            total_released: 50,
+            lock_count: 2,
+            fees_paid: 9,
+        }
+    );
+}
+
+#[test]
+fn test_upgrade_requires_initialization() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, LockAndReleaseContract);
+    let client = LockAndReleaseContractClient::new(&env, &contract_id);
+
+    let wasm_hash = BytesN::from_array(&env, &[1; 32]);
+    assert_eq!(
+        client.try_upgrade(&wasm_hash),
+        Err(Ok(ExtraError::NotInitialized.into()))
+    );
+}
+
+#[test]
+fn test_release_must_match_mapped_dest_token() {
+    let env = Env::default();
+    let (client, owner, admin, token) = setup(&env);
+    let other_token = env.register_stellar_asset_contract_v2(owner).address();
+    let lock_id = import_lock_for_release(&env, &client, &token);
+    let user = Address::generate(&env);
+    fund(&env, &token, &admin, 1_000);
+    fund(&env, &other_token, &admin, 1_000);
+
+    client.set_enforce_dest_token_match(&true);
+    assert!(client.get_enforce_dest_token_match());
+
+    // Unmapped dest tokens cannot be released
+    assert_eq!(
+        client.try_release(&admin, &lock_id, &100, &user, &token),
+        Err(Ok(ExtraError::DestTokenMismatch.into()))
+    );
+
+    let usdc = String::from_str(&env, "USDC");
+    client.set_dest_token_address(&usdc, &token);
+    assert_eq!(client.get_dest_token_address(&usdc), Some(token.clone()));
+    assert_eq!(
+        client.try_release(&admin, &lock_id, &100, &user, &other_token),
+        Err(Ok(ExtraError::DestTokenMismatch.into()))
+    );
+    client.release(&admin, &lock_id, &100, &user, &token);
+
+    // With the check off any token is accepted
+    client.set_enforce_dest_token_match(&false);
+    client.release(&admin, &lock_id, &100, &user, &other_token);
+}
+
+#[test]
+fn test_lock_amount_bounds() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 10_000);
+
+    // Unbounded above by default
+    lock_default(&env, &client, &user, &token, 5_000);
+    assert_eq!(client.get_limits().max_lock, None);
+
+    assert_eq!(
+        client.try_update_lock_limits(&100, &50),
+        Err(Ok(LockError::InvalidConfig.into()))
+    );
+    client.update_lock_limits(&100, &1_000);
+    assert_eq!(client.get_limits().min_lock, 100);
+    assert_eq!(client.get_limits().max_lock, Some(1_000));
+
+    let lock = |in_amount: i128| {
+        client.try_lock(
+            &user,
+            &token,
+            &String::from_str(&env, "USDC"),
+            &in_amount,
+            &Bytes::from_slice(&env, b"eth"),
+            &String::from_str(&env, "0xabc"),
+            &true,
+            &None,
+            &None,
+            &0,
+        )
+    };
+    assert_eq!(lock(99), Err(Ok(ExtraError::AmountBelowMinimum.into())));
+    assert_eq!(lock(1_001), Err(Ok(ExtraError::AmountAboveMaximum.into())));
+    assert!(lock(100).is_ok());
+    assert!(lock(1_000).is_ok());
+}
+
+#[test]
+fn test_refund_batch_skips_settled_locks() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    let first = lock_default(&env, &client, &user, &token, 100);
+    let second = lock_default(&env, &client, &user, &token, 200);
+    let already_refunded = lock_default(&env, &client, &user, &token, 300);
+    client.refund(&already_refunded);
+
+    let refunded = client.refund_batch(&vec![&env, first, already_refunded, second, 99]);
+    assert_eq!(refunded, vec![&env, first, second]);
+    assert_eq!(client.status_counts().refunded, 3);
+    assert_eq!(token::Client::new(&env, &token).balance(&user), 1_000);
+    assert!(has_event(&env, "RefundBatchEvent"));
+
+    client.set_max_batch_size(&1);
+    assert_eq!(
+        client.try_refund_batch(&vec![&env, first, second]),
+        Err(Ok(LockError::BatchTooLarge.into()))
+    );
+}
+
+#[test]
+fn test_is_token_supported_follows_allow_list() {
+    let env = Env::default();
+    let (client, owner, _admin, token) = setup(&env);
+    let unlisted = env.register_stellar_asset_contract_v2(owner).address();
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    fund(&env, &unlisted, &user, 1_000);
+
+    assert!(client.is_token_supported(&unlisted));
+
+    client.add_allowed_token(&token);
+    assert!(client.is_token_supported(&token));
+    assert!(!client.is_token_supported(&unlisted));
+
+    lock_default(&env, &client, &user, &token, 100);
+    assert_eq!(
+        client.try_lock(
+            &user,
+            &unlisted,
+            &String::from_str(&env, "USDC"),
+            &100,
+            &Bytes::from_slice(&env, b"eth"),
+            &String::from_str(&env, "0xabc"),
+            &true,
+            &None,
+            &None,
+            &0,
+        ),
+        Err(Ok(LockError::UnsupportedToken.into()))
+    );
+}
+
+#[test]
+fn test_forwarded_fee_falls_back_down_recipient_list() {
+    let env = Env::default();
+    let (client, owner, _admin, _token) = setup(&env);
+    let asset = env.register_stellar_asset_contract_v2(owner.clone());
+    asset.issuer().set_flag(IssuerFlags::RevocableFlag);
+    let token = asset.address();
+    let user = Address::generate(&env);
+    let fee_recipient = Address::generate(&env);
+    let first_fallback = Address::generate(&env);
+    let second_fallback = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    client.set_forward_dust_threshold(&1);
+    client.change_fee_recipient(&owner, &fee_recipient);
+    client.set_fee_recipient_fallbacks(&owner, &vec![&env, first_fallback.clone(), second_fallback.clone()]);
+
+    // Neither the recipient nor the first fallback can hold the token
+    let asset_client = StellarAssetClient::new(&env, &token);
+    asset_client.set_authorized(&fee_recipient, &false);
+    asset_client.set_authorized(&first_fallback, &false);
+    lock_default(&env, &client, &user, &token, 100);
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&second_fallback), 3);
+    assert!(has_event(&env, "FeeForwardedEvent"));
+
+    // With nobody able to take it the fee is held as accrued fees
+    asset_client.set_authorized(&second_fallback, &false);
+    lock_default(&env, &client, &user, &token, 200);
+    assert_eq!(client.get_accrued_fees(&token), 6);
+}
+
+#[test]
+fn test_withdraw_fees_sweeps_accrued_fees() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    lock_default(&env, &client, &user, &token, 100);
+    lock_default(&env, &client, &user, &token, 200);
+    assert_eq!(client.get_accrued_fees(&token), 3 + 6);
+
+    assert_eq!(client.withdraw_fees(&token, &treasury), 9);
+    assert_eq!(client.get_accrued_fees(&token), 0);
+    assert_eq!(token::Client::new(&env, &token).balance(&treasury), 9);
+    assert!(has_event(&env, "FeesWithdrawnEvent"));
+
+    assert_eq!(
+        client.try_withdraw_fees(&token, &treasury),
+        Err(Ok(LockError::NothingToClaim.into()))
+    );
+}
+
+#[test]
+fn test_pause_history_is_recorded_and_bounded() {
+    let env = Env::default();
+    let (client, owner, _admin, _token) = setup(&env);
+
+    client.pause();
+    env.ledger().with_mut(|li| li.sequence_number = 10);
+    client.unpause();
+
+    let history = client.get_pause_history();
+    assert_eq!(
+        history,
+        vec![
+            &env,
+            PauseRecord {
+                paused: true,
+                ledger: 0,
+                actor: owner.clone(),
+            },
+            PauseRecord {
+                paused: false,
+                ledger: 10,
+                actor: owner.clone(),
+            },
+        ]
+    );
+
+    // Only the most recent 20 records are kept
+    for _ in 0..10 {
+        client.set_paused(&true);
+        client.set_paused(&false);
+    }
+    let history = client.get_pause_history();
+    assert_eq!(history.len(), 20);
+    assert!(history.get(0).unwrap().paused);
+    assert_eq!(history.get(0).unwrap().ledger, 10);
+}
+
+#[test]
+fn test_release_batch_checks_total_balance_first() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let lock_id = import_lock_for_release(&env, &client, &token);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let carol = Address::generate(&env);
+    fund(&env, &token, &admin, 600);
+
+    let request = |amount: i128, user: &Address| ReleaseRequest {
+        lock_id,
+        amount,
+        user: user.clone(),
+        destination_token: token.clone(),
+    };
+
+    // Each entry fits on its own, but together they exceed the admin balance
+    let oversized = vec![&env, request(100, &alice), request(200, &bob), request(301, &carol)];
+    assert_eq!(
+        client.try_release_batch(&admin, &oversized),
+        Err(Ok(LockError::InsufficientAdminBalance.into()))
+    );
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&alice), 0);
+
+    client.release_batch(
+        &admin,
+        &vec![&env, request(100, &alice), request(200, &bob), request(300, &carol)],
+    );
+    assert_eq!(token_client.balance(&alice), 100);
+    assert_eq!(token_client.balance(&bob), 200);
+    assert_eq!(token_client.balance(&carol), 300);
+    assert_eq!(client.get_lock_audit(&lock_id).released_amount, 600);
+}
+
+#[test]
+fn test_refund_reclaims_fee_from_lock_time_recipient() {
+    let env = Env::default();
+    let (client, owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    let first = Address::generate(&env);
+    let second = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    client.set_forward_dust_threshold(&1);
+    client.change_fee_recipient(&owner, &first);
+    let lock_id = lock_default(&env, &client, &user, &token, 100);
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&first), 3);
+    assert_eq!(client.get_lock_audit(&lock_id).lock_data.fee_payee, Some(first.clone()));
+
+    // The refund reverses the fee against the recipient paid at lock time
+    client.change_fee_recipient(&owner, &second);
+    client.refund(&lock_id);
+    assert_eq!(token_client.balance(&user), 1_000);
+    assert_eq!(token_client.balance(&first), 0);
+    assert_eq!(token_client.balance(&second), 0);
+}
 
 #[test]
-fn test() {
+fn test_initialize_emits_event() {
     let env = Env::default();
-    let contract_id = env.register_contract(None, HelloContract);
-    let client = HelloContractClient::new(&env, &contract_id);
+    let contract_id = env.register_contract(None, LockAndReleaseContract);
+    let client = LockAndReleaseContractClient::new(&env, &contract_id);
+    let owner = Address::generate(&env);
 
-    let words = client.hello(&symbol_short!("Dev"));
+    client.initialize(&owner, &3, &0, &1, &0);
+    let (_, topics, data) = env.events().all().last().unwrap();
+    assert_eq!(String::try_from_val(&env, &topics.get(0).unwrap()), Ok(String::from_str(&env, "InitializeEvent")));
+    assert_eq!(Address::try_from_val(&env, &topics.get(1).unwrap()), Ok(owner));
+    let (_, config): (u64, Config) = <(u64, Config)>::try_from_val(&env, &data).unwrap();
+    assert_eq!(config.fee_scaled, 3 * (FEE_SCALE / 100));
+    assert_eq!(config.min_lock, 1);
+}
+
+#[test]
+fn test_filter_unprocessed_releases_drops_settled_locks() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    fund(&env, &token, &admin, 1_000);
+
+    let released = lock_default(&env, &client, &user, &token, 100);
+    let refunded = lock_default(&env, &client, &user, &token, 200);
+    let pending = lock_default(&env, &client, &user, &token, 300);
+    client.release(&admin, &released, &97, &user, &token);
+    client.refund(&refunded);
+
+    let ids = vec![&env, released, pending, refunded, 5];
+    assert_eq!(client.filter_unprocessed_releases(&ids), vec![&env, pending]);
+
+    let mut too_many = Vec::new(&env);
+    for lock_id in 0..101u64 {
+        too_many.push_back(lock_id);
+    }
+    assert_eq!(
+        client.try_filter_unprocessed_releases(&too_many),
+        Err(Ok(LockError::TooManyItems.into()))
+    );
+}
+
+#[test]
+fn test_user_locks_track_each_users_ids() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    fund(&env, &token, &alice, 1_000);
+    fund(&env, &token, &bob, 1_000);
+
+    assert_eq!(client.get_user_locks(&alice), Vec::new(&env));
+
+    let first = lock_default(&env, &client, &alice, &token, 100);
+    let second = lock_default(&env, &client, &bob, &token, 200);
+    let third = lock_default(&env, &client, &alice, &token, 300);
+    let fourth = lock_default(&env, &client, &bob, &token, 400);
+
+    assert_eq!(client.get_user_locks(&alice), vec![&env, first, third]);
+    assert_eq!(client.get_user_locks(&bob), vec![&env, second, fourth]);
+    assert_eq!(client.get_lock(&third).in_amount, 300);
+    assert_eq!(client.get_lock(&fourth).user_address, bob);
+    assert!(client.try_get_lock(&99).is_err());
+}
+
+#[test]
+fn test_release_waits_for_min_lock_age() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    fund(&env, &token, &admin, 1_000);
+
+    client.set_min_lock_age_ledgers(&10);
+    assert_eq!(client.get_min_lock_age_ledgers(), 10);
+    let lock_id = lock_default(&env, &client, &user, &token, 100);
+
+    // One ledger short of the minimum age
+    env.ledger().with_mut(|li| li.sequence_number += 9);
+    assert_eq!(
+        client.try_release(&admin, &lock_id, &50, &user, &token),
+        Err(Ok(ExtraError::LockTooYoung.into()))
+    );
+
+    env.ledger().with_mut(|li| li.sequence_number += 1);
+    client.release(&admin, &lock_id, &50, &user, &token);
+
+    // The owner can waive the wait for a fresh lock
+    let fresh = lock_default(&env, &client, &user, &token, 200);
+    client.waive_min_lock_age(&fresh);
+    client.release(&admin, &fresh, &50, &user, &token);
+}
+
+#[test]
+fn test_cancel_lock_requires_user_and_unreleased_lock() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    fund(&env, &token, &admin, 1_000);
+
+    let cancelled = lock_default(&env, &client, &user, &token, 100);
+    client.cancel_lock(&cancelled);
+    assert_eq!(env.auths()[0].0, user);
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&user), 1_000);
+    assert!(has_event(&env, "RefundEvent"));
+
+    let released = lock_default(&env, &client, &user, &token, 100);
+    client.release(&admin, &released, &97, &user, &token);
+    assert_eq!(
+        client.try_cancel_lock(&released),
+        Err(Ok(LockError::AlreadyReleased.into()))
+    );
+}
+
+#[test]
+fn test_unique_user_count_counts_each_user_once() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    fund(&env, &token, &alice, 1_000);
+    fund(&env, &token, &bob, 1_000);
+
+    assert_eq!(client.unique_user_count(), 0);
+    lock_default(&env, &client, &alice, &token, 100);
+    lock_default(&env, &client, &alice, &token, 200);
+    assert_eq!(client.unique_user_count(), 1);
+    lock_default(&env, &client, &bob, &token, 100);
+    assert_eq!(client.unique_user_count(), 2);
+}
+
+#[test]
+fn test_lock_rejects_unregistered_chain() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    let eth = Bytes::from_slice(&env, b"eth");
+    let sol = Bytes::from_slice(&env, b"sol");
+    assert!(client.is_chain_supported(&eth));
+
+    client.register_chain(&sol);
+    assert!(!client.is_chain_supported(&eth));
+    assert_eq!(
+        client.try_register_chain(&sol),
+        Err(Ok(ExtraError::ChainAlreadyRegistered.into()))
+    );
+    let dest_token = String::from_str(&env, "USDC");
+    let recipient = String::from_str(&env, "0xabc");
+    assert_eq!(
+        client.try_lock(&user, &token, &dest_token, &100, &eth, &recipient, &false, &None, &None, &0),
+        Err(Ok(ExtraError::UnsupportedChain.into()))
+    );
+
+    client.register_chain(&eth);
+    assert!(client.is_chain_supported(&eth));
+    lock_default(&env, &client, &user, &token, 100);
+
+    client.unregister_chain(&eth);
+    assert_eq!(
+        client.try_unregister_chain(&eth),
+        Err(Ok(LockError::ChainNotFound.into()))
+    );
+}
+
+#[test]
+fn test_release_enforces_chain_release_scheme() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    fund(&env, &token, &admin, 1_000);
+
+    let eth = Bytes::from_slice(&env, b"eth");
+    client.register_chain(&eth);
+    assert_eq!(client.get_chain_release_scheme(&eth), ReleaseScheme::SingleAdmin);
+
+    // Single admin: a plain release is enough
+    let single = lock_default(&env, &client, &user, &token, 100);
+    client.release(&admin, &single, &97, &user, &token);
+
+    // Multisig: two of three signers must approve alongside the admin
+    let signers = vec![&env, Address::generate(&env), Address::generate(&env), Address::generate(&env)];
+    assert_eq!(
+        client.try_set_chain_release_scheme(&eth, &ReleaseScheme::Multisig(4, signers.clone())),
+        Err(Ok(ExtraError::InvalidReleaseScheme.into()))
+    );
+    client.set_chain_release_scheme(&eth, &ReleaseScheme::Multisig(2, signers.clone()));
+    let multisig = lock_default(&env, &client, &user, &token, 200);
+    assert_eq!(
+        client.try_release(&admin, &multisig, &50, &user, &token),
+        Err(Ok(ExtraError::ReleaseNotAuthorized.into()))
+    );
+    let one_signer = ReleaseApproval::Signers(vec![&env, signers.get(0).unwrap()]);
+    assert_eq!(
+        client.try_release_with_approval(&admin, &multisig, &50, &user, &token, &one_signer),
+        Err(Ok(ExtraError::ReleaseNotAuthorized.into()))
+    );
+    let repeated = ReleaseApproval::Signers(vec![&env, signers.get(0).unwrap(), signers.get(0).unwrap()]);
+    assert_eq!(
+        client.try_release_with_approval(&admin, &multisig, &50, &user, &token, &repeated),
+        Err(Ok(ExtraError::ReleaseNotAuthorized.into()))
+    );
+    let two_signers = ReleaseApproval::Signers(vec![&env, signers.get(0).unwrap(), signers.get(2).unwrap()]);
+    client.release_with_approval(&admin, &multisig, &50, &user, &token, &two_signers);
+    assert_eq!(env.auths().len(), 3);
+
+    // Oracle signed: the oracle signs the release it approves
+    let signing_key = SigningKey::from_bytes(&[9; 32]);
+    let public_key = BytesN::from_array(&env, &signing_key.verifying_key().to_bytes());
+    client.set_chain_release_scheme(&eth, &ReleaseScheme::OracleSigned(public_key));
+    let oracle = lock_default(&env, &client, &user, &token, 300);
+    let sign_release = |released_amount: i128, amount: i128| {
+        let message = (client.address.clone(), oracle, released_amount, amount, user.clone(), token.clone())
+            .to_xdr(&env);
+        let mut message_buf = [0u8; 256];
+        let message_len = message.len() as usize;
+        message.copy_into_slice(&mut message_buf[..message_len]);
+        BytesN::from_array(&env, &signing_key.sign(&message_buf[..message_len]).to_bytes())
+    };
+    let signature = sign_release(0, 60);
+    assert_eq!(
+        client.try_release_with_approval(&admin, &oracle, &60, &user, &token, &two_signers),
+        Err(Ok(ExtraError::ReleaseNotAuthorized.into()))
+    );
+    assert!(client
+        .try_release_with_approval(&admin, &oracle, &61, &user, &token, &ReleaseApproval::OracleSignature(signature.clone()))
+        .is_err());
+    client.release_with_approval(&admin, &oracle, &60, &user, &token, &ReleaseApproval::OracleSignature(signature.clone()));
+    assert_eq!(client.get_lock_audit(&oracle).released_amount, 60);
+
+    // The signature covers what the lock had paid out, so it cannot be replayed
+    assert!(client
+        .try_release_with_approval(&admin, &oracle, &60, &user, &token, &ReleaseApproval::OracleSignature(signature))
+        .is_err());
+    let next_signature = sign_release(60, 60);
+    client.release_with_approval(&admin, &oracle, &60, &user, &token, &ReleaseApproval::OracleSignature(next_signature));
+    assert_eq!(client.get_lock_audit(&oracle).released_amount, 120);
+}
+
+#[test]
+fn test_lock_and_release_return_results() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    fund(&env, &token, &admin, 1_000);
+
+    let first = lock_default(&env, &client, &user, &token, 100);
+    let second = lock_default(&env, &client, &user, &token, 200);
+    assert_eq!(second, first + 1);
+
+    let mut release_ids = Vec::new(&env);
+    for lock_id in [first, second] {
+        assert!(client.release(&admin, &lock_id, &50, &user, &token));
+        let (_, _, data) = env.events().all().last().unwrap();
+        let (_, (release_id, released_lock)): (u64, (u64, u64)) =
+            <(u64, (u64, u64))>::try_from_val(&env, &data).unwrap();
+        assert_eq!(released_lock, lock_id);
+        release_ids.push_back(release_id);
+    }
+    assert_eq!(release_ids.get(1).unwrap(), release_ids.get(0).unwrap() + 1);
+    assert_eq!(client.get_release(&release_ids.get(1).unwrap()).amount, 50);
+}
+
+#[test]
+fn test_bridge_commitment_matches_lock_event() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    let lock_id = lock_default(&env, &client, &user, &token, 100);
+    let (_, _, data) = env.events().all().last().unwrap();
+    let (seq, (lock_data, commitment)): (u64, (LockData, BytesN<32>)) =
+        <(u64, (LockData, BytesN<32>))>::try_from_val(&env, &data).unwrap();
+    assert_eq!(client.bridge_commitment(&lock_id), commitment);
+
+    // The destination rebuilds the commitment from the published fields
+    let expected: BytesN<32> = env
+        .crypto()
+        .sha256(
+            &(
+                client.address.clone(),
+                seq,
+                user,
+                lock_data.dest_token,
+                lock_data.swaped_amount,
+                lock_data.recipient_address,
+                lock_data.dest_chain,
+            )
+                .to_xdr(&env),
+        )
+        .into();
+    assert_eq!(commitment, expected);
+    assert!(client.try_bridge_commitment(&(lock_id + 1)).is_err());
+}
+
+#[test]
+fn test_daily_release_cap_resets_each_day() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let lock_id = import_lock_for_release(&env, &client, &token);
+    let user = Address::generate(&env);
+    fund(&env, &token, &admin, 1_000);
+
+    client.set_release_cap(&token, &300);
+    assert_eq!(client.get_release_cap(&token), 300);
+    client.release(&admin, &lock_id, &200, &user, &token);
+    client.release(&admin, &lock_id, &100, &user, &token);
+    assert_eq!(client.remaining_capacity(&token, &user).daily_release, Some(0));
+    assert_eq!(
+        client.try_release(&admin, &lock_id, &1, &user, &token),
+        Err(Ok(ExtraError::DailyCapExceeded.into()))
+    );
+
+    // A new day starts a fresh window
+    env.ledger().with_mut(|li| li.timestamp += 86_400);
+    assert_eq!(client.remaining_capacity(&token, &user).daily_release, Some(300));
+    client.release(&admin, &lock_id, &300, &user, &token);
+}
+
+#[test]
+fn test_retry_forward_tracks_attempts() {
+    let env = Env::default();
+    let (client, owner, _admin, _token) = setup(&env);
+    let asset = env.register_stellar_asset_contract_v2(owner.clone());
+    asset.issuer().set_flag(IssuerFlags::RevocableFlag);
+    let token = asset.address();
+    let user = Address::generate(&env);
+    let fee_recipient = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    client.set_forward_dust_threshold(&1);
+    client.change_fee_recipient(&owner, &fee_recipient);
+    let asset_client = StellarAssetClient::new(&env, &token);
+    asset_client.set_authorized(&fee_recipient, &false);
+
+    // The first forward fails at lock time and the fee is held
+    let stuck = lock_default(&env, &client, &user, &token, 100);
+    let delivered = lock_default(&env, &client, &user, &token, 200);
+    let state = client.get_forward_state(&stuck).unwrap();
+    assert_eq!((state.fee, state.attempts, state.forwarded_to), (3, 1, None));
+    assert!(has_event(&env, "FeeForwardFailedEvent"));
+    assert_eq!(client.get_forward_state(&delivered).unwrap().fee, 6);
+
+    // Retries are counted and capped
+    for attempts in 2..=5 {
+        env.ledger().with_mut(|li| li.sequence_number += 1);
+        assert!(!client.retry_forward(&stuck));
+        let state = client.get_forward_state(&stuck).unwrap();
+        assert_eq!(state.attempts, attempts);
+        assert_eq!(state.last_attempt_ledger, env.ledger().sequence());
+    }
+    assert_eq!(
+        client.try_retry_forward(&stuck),
+        Err(Ok(ExtraError::ForwardRetriesExhausted.into()))
+    );
+
+    // Once the recipient can hold the token the retry delivers the fee
+    asset_client.set_authorized(&fee_recipient, &true);
+    assert!(client.retry_forward(&delivered));
+    assert_eq!(
+        client.get_forward_state(&delivered).unwrap().forwarded_to,
+        Some(fee_recipient.clone())
+    );
+    assert_eq!(token::Client::new(&env, &token).balance(&fee_recipient), 6);
+    assert_eq!(client.get_accrued_fees(&token), 3);
+    assert_eq!(
+        client.try_retry_forward(&delivered),
+        Err(Ok(LockError::NothingToClaim.into()))
+    );
+}
+
+#[test]
+fn test_set_fee_bps_charges_fractional_percent() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000_000);
+
+    assert_eq!(client.try_set_fee_bps(&10_001), Err(Ok(LockError::InvalidFee.into())));
+    client.set_fee_bps(&25);
+    assert_eq!(client.get_fee_scaled(), 2_500);
+
+    let dest_chain = Bytes::from_slice(&env, b"eth");
+    assert_eq!(client.cost_breakdown(&token, &1_000_000, &dest_chain).fee, 2_500);
+    let lock_id = lock_default(&env, &client, &user, &token, 1_000_000);
+    assert_eq!(client.get_lock(&lock_id).swaped_amount, 997_500);
+    assert_eq!(token::Client::new(&env, &token).balance(&admin), 997_500);
+}
+
+#[test]
+fn test_list_paused_tokens_follows_pause_flags() {
+    let env = Env::default();
+    let (client, owner, _admin, token) = setup(&env);
+    let other = env.register_stellar_asset_contract_v2(owner).address();
+
+    assert_eq!(client.list_paused_tokens(), Vec::new(&env));
+    client.pause_token(&token, &symbol_short!("exploit"));
+    client.pause_token(&other, &symbol_short!("upgrade"));
+    client.pause_token(&token, &symbol_short!("exploit"));
+    assert_eq!(client.list_paused_tokens(), vec![&env, token.clone(), other.clone()]);
+
+    client.unpause_token(&token);
+    assert_eq!(client.list_paused_tokens(), vec![&env, other.clone()]);
+
+    // Unpausing through the token config clears the index too
+    let mut token_config = client.get_token_config(&other);
+    token_config.paused = false;
+    client.set_token_config(&other, &token_config);
+    assert_eq!(client.list_paused_tokens(), Vec::new(&env));
+}
+
+#[test]
+fn test_release_authorized_is_single_use() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let relayer = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    fund(&env, &token, &client.address, 1_000);
+
+    let lock_id = client.lock(
+        &user,
+        &token,
+        &String::from_str(&env, "USDC"),
+        &100,
+        &Bytes::from_slice(&env, b"eth"),
+        &recipient.to_string(),
+        &false,
+        &None,
+        &None,
+        &0,
+    );
+
+    // Without the admin's authorization the relayer cannot release
+    assert_eq!(
+        client.try_release_authorized(&lock_id, &relayer),
+        Err(Ok(ExtraError::ReleaseNotAuthorized.into()))
+    );
+
+    client.authorize_release(&lock_id);
+    assert_eq!(env.auths()[0].0, admin);
+    client.release_authorized(&lock_id, &relayer);
+    assert_eq!(env.auths()[0].0, relayer);
+    assert_eq!(token::Client::new(&env, &token).balance(&recipient), 97);
+
+    // The authorization was consumed
+    assert_eq!(
+        client.try_release_authorized(&lock_id, &relayer),
+        Err(Ok(ExtraError::ReleaseNotAuthorized.into()))
+    );
+}
+
+#[test]
+fn test_role_manager_assigns_roles() {
+    let env = Env::default();
+    let (client, owner, _admin, _token) = setup(&env);
+    let manager = Address::generate(&env);
+    let new_admin = Address::generate(&env);
+    let fee_recipient = Address::generate(&env);
+
+    assert_eq!(
+        client.try_add_admin(&manager, &new_admin),
+        Err(Ok(ExtraError::NotRoleAdmin.into()))
+    );
+
+    client.set_role_manager(&Some(manager.clone()));
+    assert_eq!(env.auths()[0].0, owner);
+    assert_eq!(client.get_role_manager(), Some(manager.clone()));
+
+    client.add_admin(&manager, &new_admin);
+    assert_eq!(env.auths()[0].0, manager);
+    assert!(client.get_admins().contains(&new_admin));
+    client.change_fee_recipient(&manager, &fee_recipient);
+    assert_eq!(client.get_fee_recipient(), Some(fee_recipient));
+
+    // The owner keeps its own access and can withdraw the delegation
+    client.remove_admin(&owner, &new_admin);
+    client.set_role_manager(&None);
+    assert_eq!(
+        client.try_add_admin(&manager, &new_admin),
+        Err(Ok(ExtraError::NotRoleAdmin.into()))
+    );
+}
+
+#[test]
+fn test_next_lock_id_predicts_lock_id() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    assert_eq!(client.next_lock_id(), 0);
+    let expected = client.next_lock_id();
+    assert_eq!(lock_default(&env, &client, &user, &token, 100), expected);
+    assert_eq!(client.next_lock_id(), expected + 1);
+
+    // Imports move the counter past their ids
+    import_lock_for_release(&env, &client, &token);
+    assert_eq!(client.next_lock_id(), 1_001);
+}
+
+#[test]
+fn test_extend_refund_grace_delays_admin_refunds() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    let lock_id = lock_default(&env, &client, &user, &token, 100);
+    let start = env.ledger().sequence();
+    assert_eq!(client.extend_refund_grace(&lock_id, &10), start + 10);
+    assert_eq!(env.auths()[0].0, user);
+    assert_eq!(client.extend_refund_grace(&lock_id, &5), start + 15);
+    assert_eq!(
+        client.try_extend_refund_grace(&lock_id, &0),
+        Err(Ok(LockError::InvalidAmount.into()))
+    );
+
+    // Admin refunds wait out the grace period
+    env.ledger().with_mut(|li| li.sequence_number += 14);
+    assert_eq!(
+        client.try_refund(&lock_id),
+        Err(Ok(ExtraError::RefundGraceActive.into()))
+    );
+    assert_eq!(client.refund_batch(&vec![&env, lock_id]), Vec::new(&env));
+
+    env.ledger().with_mut(|li| li.sequence_number += 1);
+    client.refund(&lock_id);
+    assert_eq!(token::Client::new(&env, &token).balance(&user), 1_000);
+}
+
+#[test]
+fn test_partly_released_lock_cannot_be_refunded() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    fund(&env, &token, &admin, 1_000);
+
+    let lock_id = lock_default(&env, &client, &user, &token, 100);
+    client.release(&admin, &lock_id, &90, &recipient, &token);
+
+    assert_eq!(
+        client.try_cancel_lock(&lock_id),
+        Err(Ok(ExtraError::PartiallyReleased.into()))
+    );
+    assert_eq!(
+        client.try_refund(&lock_id),
+        Err(Ok(ExtraError::PartiallyReleased.into()))
+    );
+    assert_eq!(client.refund_batch(&vec![&env, lock_id]), Vec::new(&env));
+
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&user), 900);
+    assert_eq!(token_client.balance(&recipient), 90);
+    assert_eq!(token_client.balance(&admin), 1_007);
+}
+
+#[test]
+fn test_custody_releases_respect_chain_release_scheme() {
+    let env = Env::default();
+    let (client, owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    fund(&env, &token, &client.address, 1_000);
+
+    let eth = Bytes::from_slice(&env, b"eth");
+    client.register_chain(&eth);
+    let signers = vec![&env, Address::generate(&env), Address::generate(&env)];
+    client.set_chain_release_scheme(&eth, &ReleaseScheme::Multisig(2, signers));
+
+    let lock_id = client.lock(
+        &user,
+        &token,
+        &String::from_str(&env, "USDC"),
+        &100,
+        &eth,
+        &recipient.to_string(),
+        &false,
+        &None,
+        &None,
+        &0,
+    );
+    assert_eq!(
+        client.try_release_partial(&lock_id, &97),
+        Err(Ok(ExtraError::ReleaseNotAuthorized.into()))
+    );
+
+    let signing_key = SigningKey::from_bytes(&[7; 32]);
+    let public_key = BytesN::from_array(&env, &signing_key.verifying_key().to_bytes());
+    client.set_proof_verifier(&owner, &public_key);
+    let signature = sign_lock(&env, &signing_key, &client.address, lock_id);
+    assert_eq!(
+        client.try_release_with_inclusion_proof(&lock_id, &signature, &Address::generate(&env)),
+        Err(Ok(ExtraError::ReleaseNotAuthorized.into()))
+    );
+    assert_eq!(token::Client::new(&env, &token).balance(&recipient), 0);
+}
+
+#[test]
+fn test_authorize_release_checks_chain_release_scheme() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    let eth = Bytes::from_slice(&env, b"eth");
+    client.register_chain(&eth);
+    let signing_key = SigningKey::from_bytes(&[9; 32]);
+    let public_key = BytesN::from_array(&env, &signing_key.verifying_key().to_bytes());
+    client.set_chain_release_scheme(&eth, &ReleaseScheme::OracleSigned(public_key));
+
+    let lock_id = client.lock(
+        &user,
+        &token,
+        &String::from_str(&env, "USDC"),
+        &100,
+        &eth,
+        &recipient.to_string(),
+        &false,
+        &None,
+        &None,
+        &0,
+    );
+    assert_eq!(
+        client.try_authorize_release(&lock_id),
+        Err(Ok(ExtraError::ReleaseNotAuthorized.into()))
+    );
+}
+
+#[test]
+fn test_daily_release_cap_covers_custody_releases() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    fund(&env, &token, &admin, 1_000);
+    fund(&env, &token, &client.address, 1_000);
+
+    client.set_release_cap(&token, &100);
+    assert_eq!(client.max_releasable(&token), 100);
+
+    let lock_to_recipient = |in_amount: i128| {
+        client.lock(
+            &user,
+            &token,
+            &String::from_str(&env, "USDC"),
+            &in_amount,
+            &Bytes::from_slice(&env, b"eth"),
+            &recipient.to_string(),
+            &false,
+            &None,
+            &None,
+            &0,
+        )
+    };
+    let lock_id = lock_to_recipient(100);
+    client.release_partial(&lock_id, &90);
+    assert_eq!(client.max_releasable(&token), 10);
+    client.release_partial(&lock_id, &7);
+    assert_eq!(client.max_releasable(&token), 3);
+
+    // Custody releases count against the same window as admin releases
+    let other = lock_to_recipient(200);
+    assert_eq!(
+        client.try_release(&admin, &other, &4, &user, &token),
+        Err(Ok(ExtraError::DailyCapExceeded.into()))
+    );
+    client.authorize_release(&other);
+    assert_eq!(
+        client.try_release_authorized(&other, &Address::generate(&env)),
+        Err(Ok(ExtraError::DailyCapExceeded.into()))
+    );
+}
+
+#[test]
+fn test_min_lock_age_applies_to_custody_releases() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    fund(&env, &token, &client.address, 1_000);
+
+    assert_eq!(client.get_limits().min_lock_age_ledgers, None);
+    client.set_min_lock_age_ledgers(&1_000);
+    assert_eq!(client.get_limits().min_lock_age_ledgers, Some(1_000));
+
+    let lock_id = client.lock(
+        &user,
+        &token,
+        &String::from_str(&env, "USDC"),
+        &100,
+        &Bytes::from_slice(&env, b"eth"),
+        &recipient.to_string(),
+        &false,
+        &None,
+        &None,
+        &0,
+    );
+    assert_eq!(
+        client.try_release_partial(&lock_id, &50),
+        Err(Ok(ExtraError::LockTooYoung.into()))
+    );
+    client.authorize_release(&lock_id);
+    assert_eq!(
+        client.try_release_authorized(&lock_id, &Address::generate(&env)),
+        Err(Ok(ExtraError::LockTooYoung.into()))
+    );
+
+    env.ledger().with_mut(|li| li.sequence_number += 1_000);
+    client.release_partial(&lock_id, &50);
+}
+
+#[test]
+fn test_custody_releases_leave_accrued_fees_alone() {
+    let env = Env::default();
+    let (client, _owner, _admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    let lock_id = client.lock(
+        &user,
+        &token,
+        &String::from_str(&env, "USDC"),
+        &100,
+        &Bytes::from_slice(&env, b"eth"),
+        &recipient.to_string(),
+        &false,
+        &None,
+        &None,
+        &0,
+    );
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&client.address), 3);
+    assert_eq!(client.get_accrued_fees(&token), 3);
+
+    // The contract's only balance is the fee it keeps for the fee recipient
+    assert_eq!(
+        client.try_release_partial(&lock_id, &3),
+        Err(Ok(LockError::InsufficientLiquidity.into()))
+    );
+
+    fund(&env, &token, &client.address, 97);
+    client.release_partial(&lock_id, &97);
+    assert_eq!(token_client.balance(&recipient), 97);
+    assert_eq!(client.withdraw_fees(&token, &treasury), 3);
+    assert_eq!(token_client.balance(&treasury), 3);
+}
+
+#[test]
+fn test_held_principal_stays_in_custody() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    let token_client = token::Client::new(&env, &token);
+
+    client.set_hold_principal(&true);
+    assert!(client.get_hold_principal());
+    let lock_id = client.lock(
+        &user,
+        &token,
+        &String::from_str(&env, "USDC"),
+        &100,
+        &Bytes::from_slice(&env, b"eth"),
+        &recipient.to_string(),
+        &false,
+        &None,
+        &None,
+        &0,
+    );
+    let refunded_id = lock_default(&env, &client, &user, &token, 200);
+    assert_eq!(token_client.balance(&admin), 0);
+    assert_eq!(token_client.balance(&client.address), 300);
+    assert_eq!(client.get_accrued_fees(&token), 3 + 6);
+
+    // Custody pays the recipient out of the held principal
+    client.release_partial(&lock_id, &97);
+    assert_eq!(token_client.balance(&recipient), 97);
+
+    // A refund returns the held principal without touching the admin
+    client.refund(&refunded_id);
+    assert_eq!(token_client.balance(&user), 900);
+    assert_eq!(token_client.balance(&admin), 0);
+
+    assert_eq!(client.withdraw_fees(&token, &treasury), 3);
+    assert_eq!(token_client.balance(&client.address), 0);
+}
+
+#[test]
+fn test_pending_actions_list_scheduled_changes() {
+    let env = Env::default();
+    let (client, _owner, _admin, _token) = setup(&env);
+    assert!(client.get_pending_actions().is_empty());
+
+    env.ledger().with_mut(|li| li.sequence_number = 40);
+    client.transfer_ownership(&Address::generate(&env));
+    let actions = client.get_pending_actions();
+    assert_eq!(actions.len(), 1);
+    let action = actions.get(0).unwrap();
+    assert_eq!(action.kind, Symbol::new(&env, "transfer_owner"));
+    assert_eq!(action.effective_ledger, 40);
+
+    client.accept_ownership();
+    assert!(client.get_pending_actions().is_empty());
+}
+
+#[test]
+fn test_refund_after_custodian_change_releases_accrued_fee() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    let custodian = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+
+    let lock_id = lock_default(&env, &client, &user, &token, 1_000);
+    assert_eq!(client.get_accrued_fees(&token), 30);
+    assert_eq!(client.get_lock_audit(&lock_id).lock_data.fee_payee, None);
+
+    // The owner moves custody, and with it the fees held so far
+    client.set_token_custodian(&token, &custodian);
+    fund(&env, &token, &custodian, 30);
+
+    client.refund(&lock_id);
+    let token_client = token::Client::new(&env, &token);
+    assert_eq!(token_client.balance(&user), 1_000);
+    assert_eq!(token_client.balance(&admin), 0);
+    assert_eq!(token_client.balance(&custodian), 0);
+    assert_eq!(client.get_accrued_fees(&token), 0);
+}
+
+#[test]
+fn test_held_principal_is_reserved_for_its_lock() {
+    let env = Env::default();
+    let (client, _owner, admin, token) = setup(&env);
+    let user = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    fund(&env, &token, &user, 1_000);
+    let token_client = token::Client::new(&env, &token);
+
+    // Locked before principal was held, so its principal went to the admin
+    let other = client.lock(
+        &user,
+        &token,
+        &String::from_str(&env, "USDC"),
+        &100,
+        &Bytes::from_slice(&env, b"eth"),
+        &recipient.to_string(),
+        &false,
+        &None,
+        &None,
+        &0,
+    );
+    client.set_hold_principal(&true);
+    let held = lock_default(&env, &client, &user, &token, 200);
+    assert_eq!(token_client.balance(&client.address), 3 + 200);
+
+    // The held principal cannot fund another lock's custody release
     assert_eq!(
-        words,
-        vec![&env, symbol_short!("Hello"), symbol_short!("Dev"),]
+        client.try_release_partial(&other, &97),
+        Err(Ok(LockError::InsufficientLiquidity.into()))
     );
+
+    client.refund(&held);
+    assert_eq!(token_client.balance(&user), 900);
+    assert_eq!(token_client.balance(&admin), 97);
 }